@@ -1,11 +1,14 @@
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{cmp::Ordering, fmt::Write};
 
 const DATA: &str = include_str!("input.txt");
 
-fn main() {
+fn main() -> Result<()> {
     let (took, result) = took::took(|| parse(DATA));
     println!("Time spent parsing: {took}");
-    let input = result;
+    let input = result?;
 
     let (took, result) = took::took(|| part_one(input.0, input.1));
     println!("Result part one: {result}");
@@ -13,11 +16,41 @@ fn main() {
 
     let (took, result) = took::took(|| parse(DATA));
     println!("Time spent parsing: {took}");
-    let input = result;
+    let input = result?;
 
     let (took, result) = took::took(|| part_two(input.0, input.1));
     println!("Result part two: {result}");
     println!("Time spent: {took}");
+
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.iter().any(|arg| arg == "--bench") {
+        benchmark()?;
+    }
+
+    if let [_, flag, path, count, a, b] = args.as_slice()
+        && flag == "--columns"
+    {
+        compare_columns(path, count.parse()?, a.parse()?, b.parse()?)?;
+    }
+
+    Ok(())
+}
+
+/// Compares two columns of a file with any number of columns, for
+/// `--columns <path> <count> <a> <b>`.
+fn compare_columns(path: &str, count: usize, a: usize, b: usize) -> Result<()> {
+    let input = std::fs::read_to_string(path)?;
+    let columns = parse_columns(&input, count)?;
+    println!(
+        "Distance between columns {a} and {b}: {}",
+        distance(&columns, a, b)?
+    );
+    println!(
+        "Similarity of column {a} to {b}: {}",
+        similarity(&columns, a, b)?
+    );
+
+    Ok(())
 }
 
 fn part_one(mut left: Vec<usize>, mut right: Vec<usize>) -> usize {
@@ -35,17 +68,180 @@ fn part_two(left: Vec<usize>, right: Vec<usize>) -> usize {
     left.iter().map(|l| l * map.get(l).unwrap_or(&0)).sum()
 }
 
-fn parse(input: &str) -> (Vec<usize>, Vec<usize>) {
-    let mut left = vec![];
-    let mut right = vec![];
-    for line in input.lines() {
-        if let Some((lnum, rnum)) = line.split_once("   ") {
-            left.push(lnum.parse::<usize>().unwrap());
-            right.push(rnum.parse::<usize>().unwrap());
+/// Both answers for sorted-in-place lists: the sorting is a radix sort using `scratch`
+/// as its buffer and the similarity is a merge of the two sorted lists, so there's no
+/// hashing and no allocation.
+fn solve_sorted(left: &mut [usize], right: &mut [usize], scratch: &mut [usize]) -> (usize, usize) {
+    radix_sort(left, scratch);
+    radix_sort(right, scratch);
+
+    let distance = left
+        .iter()
+        .zip(right.iter())
+        .map(|(l, r)| l.abs_diff(*r))
+        .sum();
+
+    (distance, similarity_sorted(left, right))
+}
+
+/// Sorts by one byte at a time, least significant first, skipping the high bytes that
+/// are zero for every value. `scratch` must be at least as long as `values`.
+fn radix_sort(values: &mut [usize], scratch: &mut [usize]) {
+    let scratch = &mut scratch[..values.len()];
+    let max = values.iter().max().copied().unwrap_or(0);
+    let passes = (usize::BITS - max.leading_zeros()).div_ceil(8);
+
+    let (mut from, mut to) = (values, scratch);
+    for pass in 0..passes {
+        let shift = pass * 8;
+        let mut offsets = [0; 256];
+        for value in from.iter() {
+            offsets[value >> shift & 0xff] += 1;
+        }
+        let mut total = 0;
+        for offset in offsets.iter_mut() {
+            (*offset, total) = (total, total + *offset);
+        }
+        for value in from.iter() {
+            let bucket = value >> shift & 0xff;
+            to[offsets[bucket]] = *value;
+            offsets[bucket] += 1;
+        }
+        (from, to) = (to, from);
+    }
+
+    // after an odd number of passes the sorted values are in the scratch buffer
+    if passes % 2 == 1 {
+        to.copy_from_slice(from);
+    }
+}
+
+/// The similarity score of two sorted lists, multiplying the lengths of the runs of
+/// equal values on both sides.
+fn similarity_sorted(left: &[usize], right: &[usize]) -> usize {
+    let (mut i, mut j) = (0, 0);
+    let mut result = 0;
+    while i < left.len() && j < right.len() {
+        match left[i].cmp(&right[j]) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                let value = left[i];
+                let start = (i, j);
+                while i < left.len() && left[i] == value {
+                    i += 1;
+                }
+                while j < right.len() && right[j] == value {
+                    j += 1;
+                }
+                result += value * (i - start.0) * (j - start.1);
+            }
+        }
+    }
+
+    result
+}
+
+/// Times both solutions on growing random inputs, to show the sorted path scales
+/// linearly with the number of lines.
+fn benchmark() -> Result<()> {
+    for lines in [1_000_000, 2_000_000, 4_000_000, 8_000_000] {
+        let data = generate(lines, 2024);
+        let (took, result) = took::took(|| parse(&data));
+        println!("Time spent parsing {lines} lines: {took}");
+        let (mut left, mut right) = result?;
+
+        let (took, result) = took::took(|| {
+            (
+                part_one(left.clone(), right.clone()),
+                part_two(left.clone(), right.clone()),
+            )
+        });
+        println!("Result {lines} lines (sort and HashMap): {result:?}");
+        println!("Time spent: {took}");
+
+        let mut scratch = vec![0; lines];
+        let (took, result) = took::took(|| solve_sorted(&mut left, &mut right, &mut scratch));
+        println!("Result {lines} lines (radix sort and merge): {result:?}");
+        println!("Time spent: {took}");
+    }
+
+    Ok(())
+}
+
+/// Random lists in the shape of the puzzle input: `lines` pairs of five digit numbers.
+fn generate(lines: usize, seed: u64) -> String {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut data = String::with_capacity(lines * 14);
+    for _ in 0..lines {
+        let (left, right): (usize, usize) = (
+            rng.random_range(10000..100000),
+            rng.random_range(10000..100000),
+        );
+        writeln!(data, "{left}   {right}").unwrap();
+    }
+
+    data
+}
+
+/// The total distance between columns `a` and `b`, as in part one.
+fn distance(columns: &[Vec<usize>], a: usize, b: usize) -> Result<usize> {
+    let (left, right) = column_pair(columns, a, b)?;
+
+    Ok(part_one(left, right))
+}
+
+/// The similarity score of column `a` against column `b`, as in part two.
+fn similarity(columns: &[Vec<usize>], a: usize, b: usize) -> Result<usize> {
+    let (left, right) = column_pair(columns, a, b)?;
+
+    Ok(part_two(left, right))
+}
+
+fn column_pair(columns: &[Vec<usize>], a: usize, b: usize) -> Result<(Vec<usize>, Vec<usize>)> {
+    match (columns.get(a), columns.get(b)) {
+        (Some(left), Some(right)) => Ok((left.clone(), right.clone())),
+        _ => bail!(
+            "Columns {a} and {b} requested, but there are only {}",
+            columns.len()
+        ),
+    }
+}
+
+fn parse(input: &str) -> Result<(Vec<usize>, Vec<usize>)> {
+    let mut columns = parse_columns(input, 2)?;
+    let right = columns.pop().unwrap();
+    let left = columns.pop().unwrap();
+
+    Ok((left, right))
+}
+
+/// Parses lines of `count` whitespace separated numbers into one `Vec` per column.
+/// Blank lines are skipped, anything else that doesn't fit is an error.
+fn parse_columns(input: &str, count: usize) -> Result<Vec<Vec<usize>>> {
+    let mut columns = vec![vec![]; count];
+    for (i, line) in input.lines().enumerate() {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.is_empty() {
+            continue;
+        }
+        if fields.len() != count {
+            bail!(
+                "Line {}: expected {count} columns, found {}: {line:?}",
+                i + 1,
+                fields.len()
+            );
+        }
+
+        for (column, field) in columns.iter_mut().zip(fields) {
+            let number = field
+                .parse::<usize>()
+                .with_context(|| format!("Line {}: invalid number {field:?}", i + 1))?;
+            column.push(number);
         }
     }
 
-    (left, right)
+    Ok(columns)
 }
 
 #[cfg(test)]
@@ -55,26 +251,104 @@ mod tests {
     const TESTDATA: &str = include_str!("test.txt");
 
     #[test]
-    fn test_part_one_testdata() {
-        let input = parse(TESTDATA);
+    fn test_part_one_testdata() -> Result<()> {
+        let input = parse(TESTDATA)?;
         assert_eq!(part_one(input.0, input.1), 11);
+
+        Ok(())
     }
 
     #[test]
-    fn test_part_one() {
-        let input = parse(DATA);
+    fn test_part_one() -> Result<()> {
+        let input = parse(DATA)?;
         assert_eq!(part_one(input.0, input.1), 2742123);
+
+        Ok(())
     }
 
     #[test]
-    fn test_part_two_testdata() {
-        let input = parse(TESTDATA);
+    fn test_part_two_testdata() -> Result<()> {
+        let input = parse(TESTDATA)?;
         assert_eq!(part_two(input.0, input.1), 31);
+
+        Ok(())
     }
 
     #[test]
-    fn test_part_two() {
-        let input = parse(DATA);
+    fn test_part_two() -> Result<()> {
+        let input = parse(DATA)?;
         assert_eq!(part_two(input.0, input.1), 21328497);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_whitespace() -> Result<()> {
+        let input = parse("3 4\n4\t3\n\n  2     5  \n")?;
+        assert_eq!(input, (vec![3, 4, 2], vec![4, 3, 5]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = parse("3   4\n4   3   1\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Line 2: expected 2 columns, found 3: \"4   3   1\""
+        );
+
+        let error = parse("3   4\n4   x3\n").unwrap_err();
+        assert_eq!(error.to_string(), "Line 2: invalid number \"x3\"");
+    }
+
+    #[test]
+    fn test_columns() -> Result<()> {
+        let columns = parse_columns("3 1 4\n4 1 3\n2 1 5\n1 1 3\n3 1 9\n3 1 3", 3)?;
+        assert_eq!(distance(&columns, 0, 2)?, 11);
+        assert_eq!(similarity(&columns, 0, 2)?, 31);
+        assert_eq!(distance(&columns, 0, 1)?, 10);
+        assert_eq!(similarity(&columns, 1, 1)?, 36);
+        assert!(distance(&columns, 0, 3).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve_sorted_testdata() -> Result<()> {
+        let (mut left, mut right) = parse(TESTDATA)?;
+        let mut scratch = vec![0; left.len()];
+        assert_eq!(solve_sorted(&mut left, &mut right, &mut scratch), (11, 31));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve_sorted_generated() -> Result<()> {
+        let (mut left, mut right) = parse(&generate(10_000, 36))?;
+        let expected = (
+            part_one(left.clone(), right.clone()),
+            part_two(left.clone(), right.clone()),
+        );
+        let mut scratch = vec![0; left.len()];
+        assert_eq!(solve_sorted(&mut left, &mut right, &mut scratch), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_radix_sort() {
+        let mut rng = StdRng::seed_from_u64(36);
+        for len in [0, 1, 2, 100, 1000] {
+            for max in [1, 255, 256, 70_000, usize::MAX] {
+                let mut values = (0..len)
+                    .map(|_| rng.random_range(0..=max))
+                    .collect::<Vec<usize>>();
+                let mut expected = values.clone();
+                expected.sort();
+                radix_sort(&mut values, &mut vec![0; len]);
+                assert_eq!(values, expected, "{len} values up to {max}");
+            }
+        }
     }
 }
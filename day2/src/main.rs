@@ -1,3 +1,5 @@
+use std::fmt::{self, Display, Formatter};
+
 const DATA: &str = include_str!("input.txt");
 
 fn main() {
@@ -16,40 +18,260 @@ fn main() {
     let (took, result) = took::took(|| part_two(&input));
     println!("Result part two: {result}");
     println!("Time spent: {took}");
+
+    if std::env::args().any(|arg| arg == "--explain") {
+        for report in &input {
+            println!("{report:?}: {}", DAMPENED.explain(report));
+        }
+    }
 }
 
 fn part_one(input: &[Vec<usize>]) -> usize {
-    input.iter().filter(|report| is_safe(report)).count()
+    count_safe(input, &SafetyPolicy::default())
+}
+
+fn part_two(input: &[Vec<usize>]) -> usize {
+    count_safe(input, &DAMPENED)
+}
+
+const DAMPENED: SafetyPolicy = SafetyPolicy {
+    min_step: 1,
+    max_step: 3,
+    allow_plateaus: false,
+    tolerance: 1,
+};
+
+fn count_safe(input: &[Vec<usize>], policy: &SafetyPolicy) -> usize {
+    input.iter().filter(|report| policy.is_safe(report)).count()
+}
+
+/// The rules a report has to follow to be considered safe: the levels all increase or
+/// all decrease, by a step within `min_step..=max_step`.
+#[derive(Clone, Copy, Debug)]
+struct SafetyPolicy {
+    min_step: usize,
+    max_step: usize,
+    /// Whether adjacent levels may be equal, regardless of `min_step`
+    allow_plateaus: bool,
+    /// How many levels may be removed to make a report safe
+    tolerance: usize,
+}
+
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        Self {
+            min_step: 1,
+            max_step: 3,
+            allow_plateaus: false,
+            tolerance: 0,
+        }
+    }
 }
 
-fn is_safe(report: &[usize]) -> bool {
-    if report[0] == report[1] {
-        return false;
+impl SafetyPolicy {
+    pub fn is_step_valid(&self, a: usize, b: usize, inc: bool) -> bool {
+        if a == b {
+            return self.allow_plateaus;
+        }
+
+        (b > a) == inc && (self.min_step..=self.max_step).contains(&a.abs_diff(b))
+    }
+
+    pub fn is_safe(&self, report: &[usize]) -> bool {
+        if self.tolerance <= 1 {
+            return [true, false]
+                .into_iter()
+                .any(|inc| self.is_safe_linear(report, inc));
+        }
+
+        self.removals(report).is_some()
     }
 
-    let inc = report[0] < report[1];
-    report.windows(2).all(|x| {
-        let (a, b) = (x[0], x[1]);
-        if inc {
-            b > a && b - a <= 3
-        } else {
-            a > b && a - b <= 3
+    /// Checks in a single pass whether removing at most one level makes the report safe
+    /// in the given direction, by tracking whether the prefix up to each level is safe
+    /// with that level kept and nothing removed (`clean`) or one level removed (`damped`).
+    /// Only valid for a tolerance of zero or one.
+    fn is_safe_linear(&self, report: &[usize], inc: bool) -> bool {
+        let step = |a: usize, b: usize| self.is_step_valid(a, b, inc);
+        if self.tolerance == 0 {
+            return report.windows(2).all(|x| step(x[0], x[1]));
         }
-    })
+        if report.len() <= 2 {
+            return true;
+        }
+
+        // prefix state at levels i - 2 and i - 1, where removing the first level is always allowed
+        let (mut clean_before, mut clean) = (true, step(report[0], report[1]));
+        let mut damped = true;
+        for i in 2..report.len() {
+            let next_damped = (damped && step(report[i - 1], report[i]))
+                || (clean_before && step(report[i - 2], report[i]));
+            clean_before = clean;
+            clean = clean && step(report[i - 1], report[i]);
+            damped = next_damped;
+        }
+
+        // the last level can be removed as well
+        clean || damped || clean_before
+    }
+
+    pub fn explain(&self, report: &[usize]) -> Verdict {
+        match self.removals(report) {
+            Some(removed) if removed.is_empty() => Verdict::Safe,
+            Some(removed) => Verdict::SafeAfterRemoving(removed),
+            None => {
+                let (first_bad_window, reason) = self
+                    .first_violation(report)
+                    .expect("an unsafe report breaks a rule");
+                Verdict::Unsafe {
+                    first_bad_window,
+                    reason,
+                }
+            }
+        }
+    }
+
+    /// The fewest levels, at most `tolerance`, to remove to make the report safe.
+    pub fn removals(&self, report: &[usize]) -> Option<Vec<usize>> {
+        [true, false]
+            .into_iter()
+            .filter_map(|inc| self.removals_dir(report, inc))
+            .min_by_key(|removed| removed.len())
+    }
+
+    /// Fills in how the levels up to and including `i` can be made safe by removing `j`
+    /// of the levels before it, with `i` kept. A level is reached either from the start,
+    /// removing everything before it, or from an earlier kept level `p` with the
+    /// `i - 1 - p` levels in between removed, so this takes O(n·k²) for tolerance k.
+    fn removals_dir(&self, report: &[usize], inc: bool) -> Option<Vec<usize>> {
+        if report.is_empty() {
+            return Some(vec![]);
+        }
+
+        let k = self.tolerance;
+        // `Some(None)` is reached from the start, `Some(Some(p))` from the kept level `p`
+        let mut reached: Vec<Vec<Option<Option<usize>>>> = vec![vec![None; k + 1]; report.len()];
+        for i in 0..report.len() {
+            for j in 0..=k.min(i) {
+                reached[i][j] = if j == i {
+                    Some(None)
+                } else {
+                    (0..=j)
+                        .filter(|r| *r < i)
+                        .map(|r| i - 1 - r)
+                        .find(|p| {
+                            reached[*p][j - (i - 1 - p)].is_some()
+                                && self.is_step_valid(report[*p], report[i], inc)
+                        })
+                        .map(Some)
+                };
+            }
+        }
+
+        // the levels after the last kept one are removed as well
+        let (mut i, mut j) = (0..=k.min(report.len() - 1)).find_map(|removed| {
+            (0..report.len()).rev().find_map(|i| {
+                let trailing = report.len() - 1 - i;
+                let j = removed.checked_sub(trailing)?;
+                (j <= k.min(i) && reached[i][j].is_some()).then_some((i, j))
+            })
+        })?;
+
+        let mut removed = (i + 1..report.len()).collect::<Vec<usize>>();
+        loop {
+            match reached[i][j] {
+                Some(Some(p)) => {
+                    removed.extend(p + 1..i);
+                    j -= i - 1 - p;
+                    i = p;
+                }
+                _ => {
+                    removed.extend(0..i);
+                    break;
+                }
+            }
+        }
+        removed.sort();
+
+        Some(removed)
+    }
+
+    /// The first pair of adjacent levels that breaks the rules without removing
+    /// anything, with the direction taken from the first change in level.
+    fn first_violation(&self, report: &[usize]) -> Option<(usize, Reason)> {
+        let inc = report
+            .windows(2)
+            .find(|x| x[0] != x[1])
+            .is_none_or(|x| x[0] < x[1]);
+
+        report.windows(2).enumerate().find_map(|(i, x)| {
+            let (a, b) = (x[0], x[1]);
+            let reason = if a == b {
+                (!self.allow_plateaus).then_some(Reason::EqualLevels)
+            } else if (b > a) != inc {
+                Some(Reason::DirectionChange)
+            } else if a.abs_diff(b) > self.max_step {
+                Some(Reason::StepTooLarge)
+            } else if a.abs_diff(b) < self.min_step {
+                Some(Reason::StepTooSmall)
+            } else {
+                None
+            };
+
+            reason.map(|reason| (i, reason))
+        })
+    }
 }
 
-fn part_two(input: &[Vec<usize>]) -> usize {
-    input.iter().filter(|report| is_safe_damper(report)).count()
+#[derive(Debug, PartialEq, Eq)]
+enum Verdict {
+    Safe,
+    /// Safe once the levels at these indices are removed
+    SafeAfterRemoving(Vec<usize>),
+    /// Unsafe even after removing levels, `first_bad_window` being the index of the first
+    /// level of the offending pair
+    Unsafe {
+        first_bad_window: usize,
+        reason: Reason,
+    },
 }
 
-fn is_safe_damper(report: &[usize]) -> bool {
-    if is_safe(report) {
-        return true;
+impl Display for Verdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Safe => write!(f, "safe"),
+            Verdict::SafeAfterRemoving(removed) => {
+                write!(f, "safe after removing level(s) {removed:?}")
+            }
+            Verdict::Unsafe {
+                first_bad_window,
+                reason,
+            } => write!(
+                f,
+                "unsafe at levels {first_bad_window} and {}: {reason}",
+                first_bad_window + 1
+            ),
+        }
     }
+}
 
-    (0..report.len())
-        .map(|i| [&report[..i], &report[i + 1..]].concat())
-        .any(|a| is_safe(&a))
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Reason {
+    DirectionChange,
+    StepTooLarge,
+    StepTooSmall,
+    EqualLevels,
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::DirectionChange => write!(f, "direction change"),
+            Reason::StepTooLarge => write!(f, "step too large"),
+            Reason::StepTooSmall => write!(f, "step too small"),
+            Reason::EqualLevels => write!(f, "equal levels"),
+        }
+    }
 }
 
 fn parse(input: &str) -> Vec<Vec<usize>> {
@@ -66,6 +288,7 @@ fn parse(input: &str) -> Vec<Vec<usize>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     const TESTDATA: &str = include_str!("test.txt");
 
@@ -88,4 +311,200 @@ mod tests {
     fn test_part_two() {
         assert_eq!(part_two(&parse(DATA)), 324);
     }
+
+    fn is_safe(report: &[usize]) -> bool {
+        if report[0] == report[1] {
+            return false;
+        }
+
+        let inc = report[0] < report[1];
+        report.windows(2).all(|x| {
+            let (a, b) = (x[0], x[1]);
+            if inc {
+                b > a && b - a <= 3
+            } else {
+                a > b && a - b <= 3
+            }
+        })
+    }
+
+    fn is_safe_damper_brute_force(report: &[usize]) -> bool {
+        if is_safe(report) {
+            return true;
+        }
+
+        (0..report.len())
+            .map(|i| [&report[..i], &report[i + 1..]].concat())
+            .any(|a| is_safe(&a))
+    }
+
+    /// Tries every way of removing up to `policy.tolerance` levels.
+    fn is_safe_brute_force(report: &[usize], policy: &SafetyPolicy) -> bool {
+        (0..1u32 << report.len())
+            .filter(|mask| mask.count_ones() as usize <= policy.tolerance)
+            .any(|mask| {
+                let kept = report
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| mask & 1 << i == 0)
+                    .map(|(_, level)| *level)
+                    .collect::<Vec<usize>>();
+                [true, false].into_iter().any(|inc| {
+                    kept.windows(2)
+                        .all(|x| policy.is_step_valid(x[0], x[1], inc))
+                })
+            })
+    }
+
+    fn random_report(rng: &mut StdRng, max_len: usize) -> Vec<usize> {
+        let len = rng.random_range(3..max_len);
+        let mut level = 50;
+        (0..len)
+            .map(|_| {
+                level = level + rng.random_range(0..9) - 4;
+                level
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_is_safe_damper_random() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let policy = SafetyPolicy {
+            tolerance: 1,
+            ..Default::default()
+        };
+        for _ in 0..100_000 {
+            let report = random_report(&mut rng, 10);
+            let expected = is_safe_damper_brute_force(&report);
+            assert_eq!(
+                policy.is_safe_linear(&report, true) || policy.is_safe_linear(&report, false),
+                expected,
+                "{report:?}"
+            );
+            assert_eq!(policy.removals(&report).is_some(), expected, "{report:?}");
+        }
+    }
+
+    #[test]
+    fn test_is_safe_policy_random() {
+        let mut rng = StdRng::seed_from_u64(33);
+        for _ in 0..20_000 {
+            let min_step = rng.random_range(0..3);
+            let policy = SafetyPolicy {
+                min_step,
+                max_step: min_step + rng.random_range(0..3),
+                allow_plateaus: rng.random_bool(0.5),
+                tolerance: rng.random_range(0..4),
+            };
+            let report = random_report(&mut rng, 11);
+            assert_eq!(
+                policy.is_safe(&report),
+                is_safe_brute_force(&report, &policy),
+                "{report:?} {policy:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_safe_policy() {
+        let plateaus = SafetyPolicy {
+            allow_plateaus: true,
+            ..Default::default()
+        };
+        assert!(plateaus.is_safe(&[1, 1, 2, 5, 5]));
+        assert!(!SafetyPolicy::default().is_safe(&[1, 1, 2, 5, 5]));
+
+        let wide = SafetyPolicy {
+            min_step: 2,
+            max_step: 10,
+            tolerance: 2,
+            ..Default::default()
+        };
+        assert!(wide.is_safe(&[1, 11, 12, 13, 21]));
+        assert!(wide.is_safe(&[1, 2, 3, 4, 5]));
+        assert!(!wide.is_safe(&[1, 2, 3, 4, 5, 6]));
+    }
+
+    #[test]
+    fn test_explain_testdata() {
+        let verdicts = parse(TESTDATA)
+            .iter()
+            .map(|report| DAMPENED.explain(report))
+            .collect::<Vec<Verdict>>();
+        assert_eq!(
+            verdicts,
+            vec![
+                Verdict::Safe,
+                Verdict::Unsafe {
+                    first_bad_window: 1,
+                    reason: Reason::StepTooLarge,
+                },
+                Verdict::Unsafe {
+                    first_bad_window: 2,
+                    reason: Reason::StepTooLarge,
+                },
+                Verdict::SafeAfterRemoving(vec![1]),
+                Verdict::SafeAfterRemoving(vec![2]),
+                Verdict::Safe,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain_reasons() {
+        let policy = SafetyPolicy::default();
+        assert_eq!(
+            policy.explain(&[1, 3, 2, 4, 5]),
+            Verdict::Unsafe {
+                first_bad_window: 1,
+                reason: Reason::DirectionChange,
+            }
+        );
+        assert_eq!(
+            policy.explain(&[8, 6, 4, 4, 1]),
+            Verdict::Unsafe {
+                first_bad_window: 2,
+                reason: Reason::EqualLevels,
+            }
+        );
+        let policy = SafetyPolicy {
+            min_step: 2,
+            tolerance: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.explain(&[1, 2, 3, 4, 5]),
+            Verdict::SafeAfterRemoving(vec![1, 3])
+        );
+        assert_eq!(
+            policy.explain(&[1, 2, 3, 4, 5, 6]),
+            Verdict::Unsafe {
+                first_bad_window: 0,
+                reason: Reason::StepTooSmall,
+            }
+        );
+    }
+
+    #[test]
+    fn test_removals_random() {
+        let mut rng = StdRng::seed_from_u64(34);
+        for _ in 0..20_000 {
+            let policy = SafetyPolicy {
+                tolerance: rng.random_range(0..4),
+                ..Default::default()
+            };
+            let report = random_report(&mut rng, 11);
+            if let Some(removed) = policy.removals(&report) {
+                let kept = report
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !removed.contains(i))
+                    .map(|(_, level)| *level)
+                    .collect::<Vec<usize>>();
+                assert!(removed.len() <= policy.tolerance, "{report:?}");
+                assert!(SafetyPolicy::default().is_safe(&kept), "{report:?}");
+            }
+        }
+    }
 }
@@ -0,0 +1,222 @@
+use anyhow::Result;
+use itertools::Itertools;
+use nom::{
+    bytes::complete::tag,
+    character::complete::{alpha1, line_ending},
+    multi::separated_list1,
+    sequence::separated_pair,
+    IResult, Parser,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
+
+const DATA: &str = include_str!("input.txt");
+
+fn main() -> Result<()> {
+    let (took, result) = took::took(|| parse_input(DATA));
+    println!("Time spent parsing: {took}");
+    let input = result?;
+
+    let (took, result) = took::took(|| part_one(&input));
+    println!("Result part one: {result}");
+    println!("Time spent: {took}");
+
+    let (took, result) = took::took(|| parse_input(DATA));
+    println!("Time spent parsing: {took}");
+    let input = result?;
+
+    let (took, result) = took::took(|| part_two(&input));
+    println!("Result part two: {result}");
+    println!("Time spent: {took}");
+
+    Ok(())
+}
+
+fn part_one(graph: &Graph<&str>) -> usize {
+    graph
+        .triangles()
+        .into_iter()
+        .filter(|triangle| triangle.iter().any(|id| graph.node(*id).starts_with('t')))
+        .count()
+}
+
+fn part_two(graph: &Graph<&str>) -> String {
+    graph
+        .max_clique()
+        .into_iter()
+        .map(|id| graph.node(id))
+        .sorted()
+        .join(",")
+}
+
+/// An undirected graph whose nodes are interned to dense ids, so the algorithms
+/// can work on `usize`s regardless of what the puzzle uses as a node label.
+struct Graph<N> {
+    nodes: Vec<N>,
+    ids: HashMap<N, usize>,
+    edges: Vec<HashSet<usize>>,
+}
+
+impl<N: Copy + Eq + Hash> Graph<N> {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![],
+            ids: HashMap::new(),
+            edges: vec![],
+        }
+    }
+
+    pub fn from_edges(edges: impl IntoIterator<Item = (N, N)>) -> Self {
+        let mut graph = Self::new();
+        for (a, b) in edges {
+            graph.add_edge(a, b);
+        }
+
+        graph
+    }
+
+    pub fn add_node(&mut self, node: N) -> usize {
+        if let Some(id) = self.ids.get(&node) {
+            return *id;
+        }
+
+        let id = self.nodes.len();
+        self.nodes.push(node);
+        self.ids.insert(node, id);
+        self.edges.push(HashSet::new());
+
+        id
+    }
+
+    pub fn add_edge(&mut self, a: N, b: N) {
+        let a = self.add_node(a);
+        let b = self.add_node(b);
+        self.edges[a].insert(b);
+        self.edges[b].insert(a);
+    }
+
+    pub fn node(&self, id: usize) -> N {
+        self.nodes[id]
+    }
+
+    pub fn neighbours(&self, id: usize) -> &HashSet<usize> {
+        &self.edges[id]
+    }
+
+    pub fn is_connected(&self, a: usize, b: usize) -> bool {
+        self.edges[a].contains(&b)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Every set of three mutually connected nodes, each listed once in ascending id order.
+    pub fn triangles(&self) -> Vec<[usize; 3]> {
+        let mut result = vec![];
+        for a in 0..self.len() {
+            for &b in self.neighbours(a).iter().filter(|b| **b > a) {
+                for &c in self.neighbours(b).iter().filter(|c| **c > b) {
+                    if self.is_connected(a, c) {
+                        result.push([a, b, c]);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn max_clique(&self) -> Vec<usize> {
+        let mut best = vec![];
+        self.bron_kerbosch(
+            &mut vec![],
+            (0..self.len()).collect(),
+            HashSet::new(),
+            &mut best,
+        );
+
+        best
+    }
+
+    /// Bron–Kerbosch with pivoting: `r` is the clique being built, `p` the candidates
+    /// that can still extend it and `x` the nodes already tried at this level.
+    fn bron_kerbosch(
+        &self,
+        r: &mut Vec<usize>,
+        mut p: HashSet<usize>,
+        mut x: HashSet<usize>,
+        best: &mut Vec<usize>,
+    ) {
+        if p.is_empty() {
+            if x.is_empty() && r.len() > best.len() {
+                *best = r.clone();
+            }
+            return;
+        }
+
+        let pivot = *p
+            .union(&x)
+            .max_by_key(|u| self.neighbours(**u).intersection(&p).count())
+            .unwrap();
+        let candidates = p
+            .difference(self.neighbours(pivot))
+            .copied()
+            .collect::<Vec<usize>>();
+
+        for v in candidates {
+            let neighbours = self.neighbours(v);
+            r.push(v);
+            self.bron_kerbosch(
+                r,
+                p.intersection(neighbours).copied().collect(),
+                x.intersection(neighbours).copied().collect(),
+                best,
+            );
+            r.pop();
+
+            p.remove(&v);
+            x.insert(v);
+        }
+    }
+}
+
+fn parse_input(input: &'static str) -> Result<Graph<&'static str>> {
+    let (_, input) = parse(input)?;
+
+    Ok(Graph::from_edges(input))
+}
+
+fn parse(input: &str) -> IResult<&str, Vec<(&str, &str)>> {
+    separated_list1(line_ending, separated_pair(alpha1, tag("-"), alpha1)).parse(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TESTDATA: &str = include_str!("test.txt");
+
+    #[test]
+    fn test_part_one_testdata() -> Result<()> {
+        assert_eq!(part_one(&parse_input(TESTDATA)?), 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two_testdata() -> Result<()> {
+        assert_eq!(part_two(&parse_input(TESTDATA)?), "co,de,ka,ta");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_triangles_testdata() -> Result<()> {
+        assert_eq!(parse_input(TESTDATA)?.triangles().len(), 12);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{alphanumeric1, line_ending, one_of},
+    combinator::{map, value},
+    multi::separated_list1,
+    sequence::{pair, separated_pair},
+    IResult, Parser,
+};
+use std::{collections::HashMap, fmt::Write};
+
+const DATA: &str = include_str!("input.txt");
+
+fn main() -> Result<()> {
+    let (took, result) = took::took(|| parse_input(DATA));
+    println!("Time spent parsing: {took}");
+    let input = result?;
+
+    let (took, result) = took::took(|| part_one(&input));
+    println!("Result part one: {}", result?);
+    println!("Time spent: {took}");
+
+    let (took, result) = took::took(|| parse_input(DATA));
+    println!("Time spent parsing: {took}");
+    let input = result?;
+
+    let (took, result) = took::took(|| part_two(&input));
+    println!("Result part two: {result}");
+    println!("Time spent: {took}");
+
+    if let Some(path) = std::env::args().skip_while(|arg| arg != "--dot").nth(1) {
+        std::fs::write(&path, input.to_dot())?;
+        println!("Circuit written to {path}");
+    }
+
+    Ok(())
+}
+
+fn part_one(circuit: &Circuit) -> Result<u64> {
+    circuit.evaluate()
+}
+
+fn part_two(circuit: &Circuit) -> String {
+    circuit.swapped_wires().join(",")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    And,
+    Or,
+    Xor,
+}
+
+impl Op {
+    pub fn apply(self, a: bool, b: bool) -> bool {
+        match self {
+            Op::And => a && b,
+            Op::Or => a || b,
+            Op::Xor => a ^ b,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::And => "AND",
+            Op::Or => "OR",
+            Op::Xor => "XOR",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Gate<'a> {
+    a: &'a str,
+    op: Op,
+    b: &'a str,
+    out: &'a str,
+}
+
+impl Gate<'_> {
+    pub fn has_input(&self, wire: &str) -> bool {
+        self.a == wire || self.b == wire
+    }
+
+    pub fn has_input_xy(&self) -> bool {
+        is_input(self.a) && is_input(self.b)
+    }
+
+    pub fn is_first_bit(&self) -> bool {
+        self.has_input("x00") && self.has_input("y00")
+    }
+}
+
+fn is_input(wire: &str) -> bool {
+    wire.starts_with('x') || wire.starts_with('y')
+}
+
+#[derive(Debug)]
+struct Circuit<'a> {
+    wires: Vec<(&'a str, bool)>,
+    gates: Vec<Gate<'a>>,
+}
+
+impl Circuit<'_> {
+    /// Runs the gates until every wire has a value and returns the number formed by the
+    /// `z` wires, `z00` being the least significant bit.
+    pub fn evaluate(&self) -> Result<u64> {
+        let mut values = self.wires.iter().copied().collect::<HashMap<&str, bool>>();
+        let mut pending = self.gates.iter().collect::<Vec<&Gate>>();
+        while !pending.is_empty() {
+            let before = pending.len();
+            pending.retain(|gate| match (values.get(gate.a), values.get(gate.b)) {
+                (Some(a), Some(b)) => {
+                    values.insert(gate.out, gate.op.apply(*a, *b));
+                    false
+                }
+                _ => true,
+            });
+            if pending.len() == before {
+                bail!(
+                    "Circuit does not settle, unresolved gates: {}",
+                    pending.iter().map(|gate| gate.out).join(",")
+                );
+            }
+        }
+
+        let result = values
+            .into_iter()
+            .filter(|(wire, _)| wire.starts_with('z'))
+            .sorted()
+            .rev()
+            .fold(0, |acc, (_, bit)| acc << 1 | bit as u64);
+
+        Ok(result)
+    }
+
+    /// Checks every gate against the shape of a ripple-carry adder and returns the sorted
+    /// outputs that cannot be in their place:
+    /// - a `z` wire is always produced by an XOR, apart from the final carry which is an OR
+    /// - an XOR that does not take `x`/`y` inputs must produce a `z` wire
+    /// - an XOR of `x`/`y` feeds the XOR producing its `z` wire
+    /// - an AND feeds the OR producing the carry
+    ///
+    /// The first bit is a half adder, so its gates are exempt from the last two rules.
+    pub fn swapped_wires(&self) -> Vec<&str> {
+        let last_z = self
+            .gates
+            .iter()
+            .map(|gate| gate.out)
+            .filter(|wire| wire.starts_with('z'))
+            .max()
+            .unwrap_or_default();
+        let feeds = |wire: &str, op: Op| {
+            self.gates
+                .iter()
+                .any(|gate| gate.op == op && gate.has_input(wire))
+        };
+
+        self.gates
+            .iter()
+            .filter(|gate| {
+                let expected_z_op = if gate.out == last_z { Op::Or } else { Op::Xor };
+                if gate.out.starts_with('z') && gate.op != expected_z_op {
+                    return true;
+                }
+
+                match gate.op {
+                    Op::Xor if !gate.has_input_xy() => !gate.out.starts_with('z'),
+                    Op::Xor => !gate.is_first_bit() && !feeds(gate.out, Op::Xor),
+                    Op::And => !gate.is_first_bit() && !feeds(gate.out, Op::Or),
+                    Op::Or => false,
+                }
+            })
+            .map(|gate| gate.out)
+            .sorted()
+            .collect()
+    }
+
+    /// Renders the circuit as a Graphviz digraph, with the wires reported by
+    /// [`Circuit::swapped_wires`] highlighted.
+    pub fn to_dot(&self) -> String {
+        let swapped = self.swapped_wires();
+        let mut dot = String::from("digraph circuit {\n    rankdir=LR;\n");
+        for (wire, _) in &self.wires {
+            writeln!(dot, "    {wire} [shape=box];").unwrap();
+        }
+        for gate in &self.gates {
+            let colour = if swapped.contains(&gate.out) {
+                "red"
+            } else {
+                "black"
+            };
+            let gate_name = format!("{}_{}", gate.op.name(), gate.out);
+            writeln!(
+                dot,
+                "    {gate_name} [label=\"{}\", shape=ellipse];",
+                gate.op.name()
+            )
+            .unwrap();
+            writeln!(dot, "    {} -> {gate_name};", gate.a).unwrap();
+            writeln!(dot, "    {} -> {gate_name};", gate.b).unwrap();
+            writeln!(dot, "    {gate_name} -> {} [color={colour}];", gate.out).unwrap();
+            if gate.out.starts_with('z') {
+                writeln!(dot, "    {} [shape=box, color={colour}];", gate.out).unwrap();
+            } else {
+                writeln!(
+                    dot,
+                    "    {} [shape=plaintext, fontcolor={colour}];",
+                    gate.out
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+}
+
+fn parse_input(input: &'static str) -> Result<Circuit<'static>> {
+    let (_, input) = parse(input)?;
+
+    Ok(input)
+}
+
+fn parse(input: &str) -> IResult<&str, Circuit<'_>> {
+    map(
+        separated_pair(
+            separated_list1(line_ending, parse_wire),
+            pair(line_ending, line_ending),
+            separated_list1(line_ending, parse_gate),
+        ),
+        |(wires, gates)| Circuit { wires, gates },
+    )
+    .parse(input)
+}
+
+fn parse_wire(input: &str) -> IResult<&str, (&str, bool)> {
+    separated_pair(alphanumeric1, tag(": "), map(one_of("01"), |c| c == '1')).parse(input)
+}
+
+fn parse_gate(input: &str) -> IResult<&str, Gate<'_>> {
+    map(
+        (
+            alphanumeric1,
+            tag(" "),
+            parse_op,
+            tag(" "),
+            alphanumeric1,
+            tag(" -> "),
+            alphanumeric1,
+        ),
+        |(a, _, op, _, b, _, out)| Gate { a, op, b, out },
+    )
+    .parse(input)
+}
+
+fn parse_op(input: &str) -> IResult<&str, Op> {
+    alt((
+        value(Op::And, tag("AND")),
+        value(Op::Or, tag("OR")),
+        value(Op::Xor, tag("XOR")),
+    ))
+    .parse(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TESTDATA: &str = include_str!("test.txt");
+
+    /// Builds a correct `bits`-wide ripple-carry adder for `x + y`, then swaps the
+    /// outputs of the given pairs of wires.
+    fn adder(bits: usize, x: u64, y: u64, swaps: &[(&str, &str)]) -> String {
+        let mut input = String::new();
+        for (name, number) in [('x', x), ('y', y)] {
+            for i in 0..bits {
+                writeln!(input, "{name}{i:02}: {}", number >> i & 1).unwrap();
+            }
+        }
+        input.push('\n');
+
+        let mut gates = vec![
+            "x00 XOR y00 -> z00".to_string(),
+            "x00 AND y00 -> c00".to_string(),
+        ];
+        for i in 1..bits {
+            let carry = format!("c{:02}", i - 1);
+            let out = if i == bits - 1 {
+                format!("z{bits:02}")
+            } else {
+                format!("c{i:02}")
+            };
+            gates.push(format!("x{i:02} XOR y{i:02} -> a{i:02}"));
+            gates.push(format!("y{i:02} AND x{i:02} -> b{i:02}"));
+            gates.push(format!("a{i:02} XOR {carry} -> z{i:02}"));
+            gates.push(format!("{carry} AND a{i:02} -> d{i:02}"));
+            gates.push(format!("b{i:02} OR d{i:02} -> {out}"));
+        }
+
+        for gate in gates {
+            let (expr, out) = gate.split_once(" -> ").unwrap();
+            let out = swaps
+                .iter()
+                .find_map(|(a, b)| match out {
+                    _ if out == *a => Some(*b),
+                    _ if out == *b => Some(*a),
+                    _ => None,
+                })
+                .unwrap_or(out);
+            writeln!(input, "{expr} -> {out}").unwrap();
+        }
+
+        input
+    }
+
+    #[test]
+    fn test_part_one_testdata() -> Result<()> {
+        assert_eq!(part_one(&parse_input(TESTDATA)?)?, 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_one_adder() -> Result<()> {
+        let input = adder(12, 1234, 2345, &[]);
+        let (_, circuit) = parse(&input).map_err(|e| e.to_owned())?;
+        assert_eq!(part_one(&circuit)?, 3579);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two_adder() -> Result<()> {
+        let input = adder(12, 0, 0, &[]);
+        let (_, circuit) = parse(&input).map_err(|e| e.to_owned())?;
+        assert_eq!(part_two(&circuit), "");
+
+        let swaps = [
+            ("a03", "b03"),
+            ("z05", "d05"),
+            ("z07", "c07"),
+            ("a09", "z09"),
+        ];
+        let input = adder(12, 0, 0, &swaps);
+        let (_, circuit) = parse(&input).map_err(|e| e.to_owned())?;
+        assert_eq!(part_two(&circuit), "a03,a09,b03,c07,d05,z05,z07,z09");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_dot_highlights_swaps() -> Result<()> {
+        let input = adder(4, 0, 0, &[("z02", "d02")]);
+        let (_, circuit) = parse(&input).map_err(|e| e.to_owned())?;
+        let dot = circuit.to_dot();
+        assert!(dot.starts_with("digraph circuit {"));
+        assert!(dot.contains("AND_z02 -> z02 [color=red];"));
+        assert!(dot.contains("XOR_d02 -> d02 [color=red];"));
+        assert!(dot.contains("OR_c01 -> c01 [color=black];"));
+
+        Ok(())
+    }
+}
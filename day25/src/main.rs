@@ -0,0 +1,117 @@
+use anyhow::{bail, Result};
+use grid::grid;
+use nom::{
+    character::complete::{line_ending, one_of},
+    combinator::map,
+    multi::separated_list1,
+    sequence::pair,
+    IResult, Parser,
+};
+
+const DATA: &str = include_str!("input.txt");
+
+const WIDTH: usize = 5;
+const HEIGHT: usize = 7;
+
+type Heights = [usize; WIDTH];
+
+fn main() -> Result<()> {
+    let (took, result) = took::took(|| parse_input(DATA));
+    println!("Time spent parsing: {took}");
+    let input = result?;
+
+    let (took, result) = took::took(|| part_one(&input));
+    println!("Result part one: {result}");
+    println!("Time spent: {took}");
+
+    Ok(())
+}
+
+fn part_one(schematics: &Schematics) -> usize {
+    schematics
+        .locks
+        .iter()
+        .map(|lock| schematics.keys.iter().filter(|key| fits(lock, key)).count())
+        .sum()
+}
+
+fn fits(lock: &Heights, key: &Heights) -> bool {
+    lock.iter().zip(key).all(|(l, k)| l + k <= HEIGHT - 2)
+}
+
+#[derive(Debug, Default)]
+struct Schematics {
+    locks: Vec<Heights>,
+    keys: Vec<Heights>,
+}
+
+impl Schematics {
+    pub fn new(grids: Vec<Vec<Vec<bool>>>) -> Result<Self> {
+        let mut schematics = Self::default();
+        for grid in grids {
+            if grid.len() != HEIGHT || grid.iter().any(|row| row.len() != WIDTH) {
+                bail!("Schematic is not {WIDTH}x{HEIGHT}: {grid:?}");
+            }
+
+            let heights = heights(&grid);
+            if grid[0].iter().all(|filled| *filled) {
+                schematics.locks.push(heights);
+            } else if grid[HEIGHT - 1].iter().all(|filled| *filled) {
+                schematics.keys.push(heights);
+            } else {
+                bail!("Schematic is neither a lock nor a key: {grid:?}");
+            }
+        }
+
+        Ok(schematics)
+    }
+}
+
+/// The number of filled cells per column, not counting the solid top or bottom row.
+fn heights(grid: &[Vec<bool>]) -> Heights {
+    let mut heights = [0; WIDTH];
+    for row in &grid[1..HEIGHT - 1] {
+        for (height, filled) in heights.iter_mut().zip(row) {
+            *height += *filled as usize;
+        }
+    }
+
+    heights
+}
+
+fn parse_input(input: &'static str) -> Result<Schematics> {
+    let (_, input) = parse(input)?;
+
+    Schematics::new(input)
+}
+
+fn parse(input: &str) -> IResult<&str, Vec<Vec<Vec<bool>>>> {
+    let schematic = grid(map(one_of("#."), |c| c == '#'));
+    separated_list1(pair(line_ending, line_ending), schematic).parse(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TESTDATA: &str = include_str!("test.txt");
+
+    #[test]
+    fn test_part_one_testdata() -> Result<()> {
+        assert_eq!(part_one(&parse_input(TESTDATA)?), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_heights_testdata() -> Result<()> {
+        let schematics = parse_input(TESTDATA)?;
+        assert_eq!(schematics.locks, vec![[0, 5, 3, 4, 3], [1, 2, 0, 5, 3]]);
+        assert_eq!(
+            schematics.keys,
+            vec![[5, 0, 2, 1, 3], [4, 3, 4, 0, 2], [3, 0, 2, 0, 1]]
+        );
+
+        Ok(())
+    }
+}
@@ -1,60 +1,95 @@
+use anyhow::{anyhow, bail, Context, Result};
 use nom::{
-    branch::alt,
-    bytes::complete::tag,
-    character::complete::{self, anychar},
+    bytes::complete::{tag, take_while_m_n},
+    character::complete::digit1,
     combinator::map,
-    multi::{many0, many1, many_till},
-    sequence::{delimited, separated_pair, terminated},
+    error::{Error, ErrorKind},
+    multi::separated_list0,
+    sequence::delimited,
     IResult, Parser,
 };
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{BufRead, BufReader},
+    ops::Range,
+};
 
 const DATA: &str = include_str!("input.txt");
 
-fn main() {
+fn main() -> Result<()> {
+    let (took, result) = took::took(|| part_one_vm(DATA));
+    println!("Result part one: {}", result?);
+    println!("Time spent: {took}");
+
     let (took, result) = took::took(|| parse_input(DATA, false));
-    println!("Time spent parsing: {took}");
+    println!("Time spent parsing baseline: {took}");
     let input = result;
 
     let (took, result) = took::took(|| solve(&input));
-    println!("Result part one: {result}");
+    println!("Result part one baseline: {}", result?);
     println!("Time spent: {took}");
 
-    let (took, result) = took::took(|| parse_nom(DATA));
-    println!("Time spent parsing: {took}");
-    let (_, input) = result.unwrap();
-
-    let (took, result) = took::took(|| part_one_nom(&input));
-    println!("Result part one nom: {result}");
+    let (took, result) = took::took(|| part_two_vm(DATA));
+    println!("Result part two: {}", result?);
     println!("Time spent: {took}");
 
     let (took, result) = took::took(|| parse_input(DATA, true));
-    println!("Time spent parsing: {took}");
+    println!("Time spent parsing baseline: {took}");
     let input = result;
 
     let (took, result) = took::took(|| solve(&input));
-    println!("Result part two: {result}");
+    println!("Result part two baseline: {}", result?);
     println!("Time spent: {took}");
 
-    let (took, result) = took::took(|| parse_nom(DATA));
-    println!("Time spent parsing: {took}");
-    let (_, input) = result.unwrap();
+    if let Some(path) = std::env::args().skip_while(|arg| arg != "--stream").nth(1) {
+        let reader = BufReader::new(File::open(&path).with_context(|| format!("Opening {path}"))?);
+        let (took, result) = took::took(|| InstructionSet::part_two().run_reader(reader));
+        println!("Result part two streamed from {path}: {}", result?);
+        println!("Time spent: {took}");
+    }
+
+    if std::env::args().any(|arg| arg == "--report") {
+        InstructionSet::part_two()
+            .report(DATA.as_bytes())?
+            .print(DATA.as_bytes());
+    }
 
-    let (took, result) = took::took(|| part_two_nom(&input));
-    println!("Result part two nom: {result}");
-    println!("Time spent: {took}");
+    Ok(())
 }
 
-fn solve(input: &[Mul]) -> u32 {
-    input.iter().map(|m| m.a * m.b).sum()
+fn solve(input: &[Mul]) -> Result<u64> {
+    input.iter().try_fold(0, |acc, m| m.add_to(acc))
 }
 
 #[derive(Debug)]
 struct Mul {
-    a: u32,
-    b: u32,
+    a: u64,
+    b: u64,
+    /// Byte offset of the instruction in the corrupted memory
+    offset: usize,
+}
+
+impl Mul {
+    /// Adds the product to `acc`, failing on overflow instead of wrapping.
+    pub fn add_to(&self, acc: u64) -> Result<u64> {
+        self.a
+            .checked_mul(self.b)
+            .and_then(|product| acc.checked_add(product))
+            .ok_or_else(|| {
+                anyhow!(
+                    "mul({},{}) at offset {} overflows",
+                    self.a,
+                    self.b,
+                    self.offset
+                )
+            })
+    }
 }
 
-fn parse_input(input: &'static str, has_ignore: bool) -> Vec<Mul> {
+/// Scans the memory with plain string matching. Kept as the baseline the instruction
+/// set is measured and fuzzed against.
+fn parse_input(input: &str, has_ignore: bool) -> Vec<Mul> {
     let mut vec = vec![];
     let mut ignore = false;
     for i in 0..input.len() {
@@ -72,140 +107,743 @@ fn parse_input(input: &'static str, has_ignore: bool) -> Vec<Mul> {
             continue;
         }
 
-        if input[i..].starts_with("mul(") {
-            if let Some(paren) = input[i..].find(')') {
-                if let Some((a, b)) = input[i + 4..paren + i].split_once(',') {
-                    let a = match a.parse::<u32>() {
-                        Ok(val) => val,
-                        Err(_) => continue,
-                    };
-
-                    let b = match b.parse::<u32>() {
-                        Ok(val) => val,
-                        Err(_) => continue,
-                    };
-
-                    vec.push(Mul { a, b });
-                }
-            }
+        if input[i..].starts_with("mul(")
+            && let Some(paren) = input[i..].find(')')
+            && let Some((a, b)) = input[i + 4..paren + i].split_once(',')
+            && let Some(a) = parse_operand_str(a)
+            && let Some(b) = parse_operand_str(b)
+        {
+            vec.push(Mul { a, b, offset: i });
         }
     }
 
     vec
 }
 
+/// Parses one to three ASCII digits, refusing signs and anything longer.
+fn parse_operand_str(operand: &str) -> Option<u64> {
+    if operand.is_empty()
+        || operand.len() > MAX_DIGITS
+        || !operand.bytes().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    operand.parse().ok()
+}
+
+fn part_one_vm(input: &str) -> Result<u64> {
+    InstructionSet::part_one().run(input.as_bytes())
+}
+
+fn part_two_vm(input: &str) -> Result<u64> {
+    InstructionSet::part_two().run(input.as_bytes())
+}
+
+/// The state the instructions act on.
 #[derive(Debug)]
-enum Instr {
-    Mul(Mul),
-    Do,
-    Dont,
-}
-
-fn part_one_nom(input: &[Instr]) -> u32 {
-    input
-        .iter()
-        .filter_map(|m| match m {
-            Instr::Mul(m) => Some(m.a * m.b),
-            Instr::Do => None,
-            Instr::Dont => None,
-        })
-        .sum()
+struct Machine {
+    enabled: bool,
+    accumulator: u64,
 }
 
-fn part_two_nom(input: &[Instr]) -> u32 {
-    let mut result = 0;
-    let mut ignore = false;
-    for instr in input {
-        match instr {
-            Instr::Mul(mul) => {
-                if !ignore {
-                    result += mul.a * mul.b
+impl Machine {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            accumulator: 0,
+        }
+    }
+
+    /// Adds `value` to the accumulator if the machine is enabled, where `None` is a
+    /// calculation that already overflowed.
+    pub fn accumulate(&mut self, value: Option<u64>) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        match value.and_then(|value| self.accumulator.checked_add(value)) {
+            Some(accumulator) => self.accumulator = accumulator,
+            None => bail!("overflow"),
+        }
+
+        Ok(())
+    }
+}
+
+type Semantics = fn(&mut Machine, &[u64]) -> Result<()>;
+
+struct Instruction {
+    name: &'static str,
+    arity: usize,
+    semantics: Semantics,
+}
+
+/// The instructions recognised in the corrupted memory, tried in order of registration.
+struct InstructionSet {
+    instructions: Vec<Instruction>,
+}
+
+impl InstructionSet {
+    pub fn new() -> Self {
+        Self {
+            instructions: vec![],
+        }
+    }
+
+    pub fn register(mut self, name: &'static str, arity: usize, semantics: Semantics) -> Self {
+        self.instructions.push(Instruction {
+            name,
+            arity,
+            semantics,
+        });
+
+        self
+    }
+
+    pub fn part_one() -> Self {
+        Self::new().register("mul", 2, |machine, args| {
+            machine.accumulate(args[0].checked_mul(args[1]))
+        })
+    }
+
+    pub fn part_two() -> Self {
+        Self::part_one()
+            .register("do", 0, |machine, _| {
+                machine.enabled = true;
+                Ok(())
+            })
+            .register("don't", 0, |machine, _| {
+                machine.enabled = false;
+                Ok(())
+            })
+    }
+
+    pub fn run(&self, input: &[u8]) -> Result<u64> {
+        let mut machine = Machine::new();
+        for call in self.scan(input) {
+            call.execute(&mut machine)?;
+        }
+
+        Ok(machine.accumulator)
+    }
+
+    /// Finds the registered instructions in between the garbage, skipping a byte at a
+    /// time until one of them parses.
+    pub fn scan(&self, input: &[u8]) -> Vec<Call<'_>> {
+        self.tokenize(input, false).0
+    }
+
+    /// Runs the input like [`InstructionSet::run`], but reports where every instruction
+    /// is, which tokens came close to being one and which parts of the input are enabled.
+    pub fn report(&self, input: &[u8]) -> Result<Report<'_>> {
+        let (calls, near_misses) = self.tokenize(input, true);
+
+        let mut machine = Machine::new();
+        let mut regions = vec![Region {
+            span: 0..input.len(),
+            enabled: machine.enabled,
+        }];
+        for call in &calls {
+            call.execute(&mut machine)?;
+            let region = regions.last_mut().unwrap();
+            if machine.enabled != region.enabled {
+                region.span.end = call.span.end;
+                regions.push(Region {
+                    span: call.span.end..input.len(),
+                    enabled: machine.enabled,
+                });
+            }
+        }
+
+        Ok(Report {
+            calls,
+            near_misses,
+            regions,
+            result: machine.accumulator,
+        })
+    }
+
+    /// Runs the instructions read from `reader` without holding more than a buffer and one
+    /// instruction's worth of bytes in memory.
+    pub fn run_reader(&self, reader: impl BufRead) -> Result<u64> {
+        let mut machine = Machine::new();
+        self.scan_reader(reader, |call| call.execute(&mut machine))?;
+
+        Ok(machine.accumulator)
+    }
+
+    /// Streaming version of [`InstructionSet::scan`], passing every instruction to `f` with
+    /// its span in the whole stream. Only the bytes that could still start an instruction
+    /// split across two reads are carried over.
+    pub fn scan_reader(
+        &self,
+        mut reader: impl BufRead,
+        mut f: impl FnMut(Call<'_>) -> Result<()>,
+    ) -> Result<()> {
+        let max_len = self.max_call_len();
+        let mut window = vec![];
+        // offset of `window[0]` in the stream
+        let mut offset = 0;
+        loop {
+            let chunk = reader.fill_buf()?;
+            let eof = chunk.is_empty();
+            window.extend_from_slice(chunk);
+            let read = chunk.len();
+            reader.consume(read);
+
+            let mut i = 0;
+            while i < window.len() && (eof || window.len() - i >= max_len) {
+                match self.parse_call(&window[i..]) {
+                    Ok((rest, (instruction, args))) => {
+                        let end = window.len() - rest.len();
+                        f(Call {
+                            instruction,
+                            args,
+                            span: offset + i..offset + end,
+                        })?;
+                        i = end;
+                    }
+                    Err(_) => i += 1,
                 }
             }
-            Instr::Do => ignore = false,
-            Instr::Dont => ignore = true,
+            window.drain(..i);
+            offset += i;
+
+            if eof {
+                return Ok(());
+            }
         }
     }
 
-    result
-}
+    /// The longest a call can be: its name, parentheses and the most digits and commas
+    /// its operands can take.
+    fn max_call_len(&self) -> usize {
+        self.instructions
+            .iter()
+            .map(|instruction| {
+                instruction.name.len()
+                    + 2
+                    + instruction.arity * MAX_DIGITS
+                    + instruction.arity.saturating_sub(1)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn tokenize(&self, input: &[u8], with_near_misses: bool) -> (Vec<Call<'_>>, Vec<NearMiss>) {
+        let mut calls = vec![];
+        let mut near_misses = vec![];
+        let mut i = 0;
+        while i < input.len() {
+            match self.parse_call(&input[i..]) {
+                Ok((rest, (instruction, args))) => {
+                    let end = input.len() - rest.len();
+                    calls.push(Call {
+                        instruction,
+                        args,
+                        span: i..end,
+                    });
+                    i = end;
+                }
+                Err(_) => {
+                    if with_near_misses && let Some((len, kind)) = self.near_miss(&input[i..]) {
+                        near_misses.push(NearMiss {
+                            span: i..i + len,
+                            kind,
+                        });
+                    }
+                    i += 1;
+                }
+            }
+        }
+
+        (calls, near_misses)
+    }
+
+    fn parse_call<'a, 'i>(
+        &'a self,
+        input: &'i [u8],
+    ) -> IResult<&'i [u8], (&'a Instruction, Vec<u64>)> {
+        for instruction in &self.instructions {
+            let result: IResult<&[u8], _> = (
+                tag(instruction.name.as_bytes()),
+                delimited(tag("("), separated_list0(tag(","), parse_operand), tag(")")),
+            )
+                .parse(input);
+            if let Ok((rest, (_, args))) = result
+                && args.len() == instruction.arity
+            {
+                return Ok((rest, (instruction, args)));
+            }
+        }
 
-fn parse_nom(input: &str) -> IResult<&str, Vec<Instr>> {
-    terminated(many1(parse_instr), many0(anychar)).parse(input)
+        Err(nom::Err::Error(Error::new(input, ErrorKind::Alt)))
+    }
+
+    /// Checks whether an instruction name followed by an opening parenthesis, with or
+    /// without whitespace in between, starts the input, and if so how it went wrong.
+    /// Returns the length of the token with its kind.
+    fn near_miss(&self, input: &[u8]) -> Option<(usize, NearMissKind)> {
+        let instruction = self.instructions.iter().find(|instruction| {
+            input.starts_with(instruction.name.as_bytes())
+                && input[instruction.name.len()..]
+                    .iter()
+                    .find(|c| !c.is_ascii_whitespace())
+                    .is_some_and(|c| *c == b'(')
+        })?;
+
+        // everything that could be part of a call, up to and including its closing
+        // parenthesis, but not what ends it otherwise or where a real call starts
+        let mut len = instruction.name.len();
+        while len < input.len().min(instruction.name.len() + NEAR_MISS_LENGTH) {
+            let c = input[len];
+            if c == b')' {
+                len += 1;
+                break;
+            }
+            if !(c.is_ascii_digit() || c.is_ascii_whitespace() || b"(,+-".contains(&c))
+                || self.parse_call(&input[len..]).is_ok()
+            {
+                break;
+            }
+            len += 1;
+        }
+        let token = &input[..len];
+
+        let compact = token
+            .iter()
+            .filter(|c| !c.is_ascii_whitespace())
+            .copied()
+            .collect::<Vec<u8>>();
+        let loose_args: IResult<&[u8], _> = delimited(
+            (tag(instruction.name.as_bytes()), tag("(")),
+            separated_list0(tag(","), digit1),
+            tag(")"),
+        )
+        .parse(&compact);
+
+        let kind = match loose_args {
+            Ok((_, _)) if compact.len() < token.len() && self.parse_call(&compact).is_ok() => {
+                NearMissKind::Whitespace
+            }
+            Ok((_, args)) if args.iter().any(|arg| arg.len() > MAX_DIGITS) => {
+                NearMissKind::OperandTooLong
+            }
+            Ok((_, args)) if args.len() != instruction.arity && compact.len() == token.len() => {
+                NearMissKind::WrongArity
+            }
+            _ => NearMissKind::Malformed,
+        };
+
+        Some((len, kind))
+    }
 }
 
-fn parse_instr(input: &str) -> IResult<&str, Instr> {
+/// Operands have one to three digits.
+const MAX_DIGITS: usize = 3;
+
+/// How far past an instruction name to look for the end of a near miss.
+const NEAR_MISS_LENGTH: usize = 32;
+
+fn parse_operand(input: &[u8]) -> IResult<&[u8], u64> {
     map(
-        many_till(anychar, alt((parse_do, parse_dont, parse_mul))),
-        |(_, instr)| instr,
+        take_while_m_n(1, MAX_DIGITS, |c: u8| c.is_ascii_digit()),
+        |digits: &[u8]| {
+            digits
+                .iter()
+                .fold(0, |acc, digit| acc * 10 + (digit - b'0') as u64)
+        },
     )
     .parse(input)
 }
 
-fn parse_do(input: &str) -> IResult<&str, Instr> {
-    map(tag("do()"), |_| Instr::Do).parse(input)
+/// An instruction found in the input, with its arguments and byte span.
+struct Call<'a> {
+    instruction: &'a Instruction,
+    args: Vec<u64>,
+    span: Range<usize>,
 }
 
-fn parse_dont(input: &str) -> IResult<&str, Instr> {
-    map(tag("don't()"), |_| Instr::Dont).parse(input)
+impl Call<'_> {
+    pub fn execute(&self, machine: &mut Machine) -> Result<()> {
+        (self.instruction.semantics)(machine, &self.args)
+            .with_context(|| format!("{self} at offset {} failed", self.span.start))
+    }
 }
 
-fn parse_mul(input: &str) -> IResult<&str, Instr> {
-    map(
-        delimited(
-            tag("mul("),
-            separated_pair(complete::u32, tag(","), complete::u32),
-            tag(")"),
-        ),
-        |(a, b)| Instr::Mul(Mul { a, b }),
-    )
-    .parse(input)
+impl Display for Call<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<String>>();
+        write!(f, "{}({})", self.instruction.name, args.join(","))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct NearMiss {
+    span: Range<usize>,
+    kind: NearMissKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NearMissKind {
+    /// Valid once the whitespace is removed, e.g. `mul ( 2 , 4 )`
+    Whitespace,
+    /// An operand with more than three digits, e.g. `mul(1234,5)`
+    OperandTooLong,
+    /// Well formed, but with the wrong number of operands, e.g. `mul(2)`
+    WrongArity,
+    /// Anything else, e.g. `mul(4` cut short by a `*`, or `mul(-2,4)`
+    Malformed,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Region {
+    span: Range<usize>,
+    enabled: bool,
+}
+
+struct Report<'a> {
+    calls: Vec<Call<'a>>,
+    near_misses: Vec<NearMiss>,
+    /// Consecutive spans covering the whole input, alternating between enabled and not
+    regions: Vec<Region>,
+    result: u64,
+}
+
+impl Report<'_> {
+    pub fn print(&self, input: &[u8]) {
+        let text = |span: &Range<usize>| String::from_utf8_lossy(&input[span.clone()]).into_owned();
+
+        println!("Instructions: {}", self.calls.len());
+        for call in &self.calls {
+            println!("  {:?}: {call}", call.span);
+        }
+        println!("Near misses: {}", self.near_misses.len());
+        for near_miss in &self.near_misses {
+            println!(
+                "  {:?}: {:?} {:?}",
+                near_miss.span,
+                near_miss.kind,
+                text(&near_miss.span)
+            );
+        }
+        println!("Regions: {}", self.regions.len());
+        for region in &self.regions {
+            let state = if region.enabled {
+                "enabled"
+            } else {
+                "disabled"
+            };
+            println!("  {:?}: {state}", region.span);
+        }
+        println!("Result: {}", self.result);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     const TESTDATA: &str = include_str!("test.txt");
     const TESTDATA2: &str = include_str!("test2.txt");
 
     #[test]
-    fn test_part_one_testdata() {
-        assert_eq!(solve(&parse_input(TESTDATA, false)), 161);
+    fn test_part_one_testdata() -> Result<()> {
+        assert_eq!(solve(&parse_input(TESTDATA, false))?, 161);
+
+        Ok(())
     }
 
     #[test]
-    fn test_part_one() {
-        assert_eq!(solve(&parse_input(DATA, false)), 170778545);
+    fn test_part_one() -> Result<()> {
+        assert_eq!(solve(&parse_input(DATA, false))?, 170778545);
+
+        Ok(())
     }
 
     #[test]
-    fn test_part_one_testdata_nom() {
-        assert_eq!(part_one_nom(&parse_nom(TESTDATA).unwrap().1), 161);
+    fn test_part_two_testdata() -> Result<()> {
+        assert_eq!(solve(&parse_input(TESTDATA2, true))?, 48);
+
+        Ok(())
     }
 
     #[test]
-    fn test_part_one_nom() {
-        assert_eq!(part_one_nom(&parse_nom(DATA).unwrap().1), 170778545);
+    fn test_part_two() -> Result<()> {
+        assert_eq!(solve(&parse_input(DATA, true))?, 82868252);
+
+        Ok(())
     }
 
     #[test]
-    fn test_part_two_testdata() {
-        assert_eq!(solve(&parse_input(TESTDATA2, true)), 48);
+    fn test_part_one_testdata_vm() -> Result<()> {
+        assert_eq!(part_one_vm(TESTDATA)?, 161);
+
+        Ok(())
     }
 
     #[test]
-    fn test_part_two() {
-        assert_eq!(solve(&parse_input(DATA, true)), 82868252);
+    fn test_part_one_vm() -> Result<()> {
+        assert_eq!(part_one_vm(DATA)?, 170778545);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two_testdata_vm() -> Result<()> {
+        assert_eq!(part_two_vm(TESTDATA2)?, 48);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two_vm() -> Result<()> {
+        assert_eq!(part_two_vm(DATA)?, 82868252);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_instructions() -> Result<()> {
+        let set = InstructionSet::part_two()
+            .register("add", 2, |machine, args| {
+                machine.accumulate(args[0].checked_add(args[1]))
+            })
+            .register("sub", 2, |machine, args| {
+                machine.accumulate(args[0].checked_sub(args[1]))
+            })
+            .register("toggle", 0, |machine, _| {
+                machine.enabled = !machine.enabled;
+                Ok(())
+            })
+            .register("double", 0, |machine, _| {
+                machine.accumulate(Some(machine.accumulator))
+            });
+
+        let input = b"add(1,2)xsub(5,3)]toggle()mul(2,3)add(1)toggle()double()don't()add(3,4)";
+        assert_eq!(
+            set.scan(input)
+                .iter()
+                .map(|call| call.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "add(1,2)", "sub(5,3)", "toggle()", "mul(2,3)", "toggle()", "double()", "don't()",
+                "add(3,4)"
+            ]
+        );
+        assert_eq!(set.run(input)?, 10);
+
+        let error = set.run(b"mul(2,2)sub(1,2)").unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "sub(1,2) at offset 8 failed: overflow"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_testdata() -> Result<()> {
+        let set = InstructionSet::part_two();
+        let report = set.report(TESTDATA2.as_bytes())?;
+        assert_eq!(
+            report
+                .calls
+                .iter()
+                .map(|call| (call.span.clone(), call.to_string()))
+                .collect::<Vec<(Range<usize>, String)>>(),
+            vec![
+                (1..9, "mul(2,4)".to_string()),
+                (20..27, "don't()".to_string()),
+                (28..36, "mul(5,5)".to_string()),
+                (48..57, "mul(11,8)".to_string()),
+                (59..63, "do()".to_string()),
+                (64..72, "mul(8,5)".to_string()),
+            ]
+        );
+        assert_eq!(
+            report.near_misses,
+            vec![NearMiss {
+                span: 37..46,
+                kind: NearMissKind::Malformed,
+            }]
+        );
+        assert_eq!(
+            report.regions,
+            vec![
+                Region {
+                    span: 0..27,
+                    enabled: true,
+                },
+                Region {
+                    span: 27..63,
+                    enabled: false,
+                },
+                Region {
+                    span: 63..TESTDATA2.len(),
+                    enabled: true,
+                },
+            ]
+        );
+        assert_eq!(report.result, 48);
+
+        Ok(())
     }
 
     #[test]
-    fn test_part_two_testdata_nom() {
-        assert_eq!(part_two_nom(&parse_nom(TESTDATA2).unwrap().1), 48);
+    fn test_near_misses() -> Result<()> {
+        let input = b"mul(4*xmul ( 2 , 4 )_mul(1234,5)mul(2)mul(-2,4)mul[3,7]mul(2,3)";
+        let set = InstructionSet::part_one();
+        let report = set.report(input)?;
+        assert_eq!(
+            report
+                .near_misses
+                .iter()
+                .map(|near_miss| (&input[near_miss.span.clone()], near_miss.kind))
+                .collect::<Vec<(&[u8], NearMissKind)>>(),
+            vec![
+                (&b"mul(4"[..], NearMissKind::Malformed),
+                (&b"mul ( 2 , 4 )"[..], NearMissKind::Whitespace),
+                (&b"mul(1234,5)"[..], NearMissKind::OperandTooLong),
+                (&b"mul(2)"[..], NearMissKind::WrongArity),
+                (&b"mul(-2,4)"[..], NearMissKind::Malformed),
+            ]
+        );
+        assert_eq!(report.calls.len(), 1);
+        assert_eq!(report.result, 6);
+
+        let input = b"mul(2,3mul(4,5)";
+        let report = set.report(input)?;
+        assert_eq!(
+            report
+                .calls
+                .iter()
+                .map(|call| call.span.clone())
+                .collect::<Vec<Range<usize>>>(),
+            vec![7..15]
+        );
+        assert_eq!(
+            report.near_misses,
+            vec![NearMiss {
+                span: 0..7,
+                kind: NearMissKind::Malformed,
+            }]
+        );
+
+        Ok(())
     }
 
     #[test]
-    fn test_part_two_nom() {
-        assert_eq!(part_two_nom(&parse_nom(DATA).unwrap().1), 82868252);
+    fn test_overflow() {
+        let mul = Mul {
+            a: u64::MAX,
+            b: 2,
+            offset: 31,
+        };
+        assert_eq!(
+            mul.add_to(0).unwrap_err().to_string(),
+            format!("mul({},2) at offset 31 overflows", u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_operand_digits() -> Result<()> {
+        let input = "mul(1234,5)mul(+5,3)mul(-5,3)mul(123,4)mul(,4)mul(0001,2)mul(7,8)";
+        assert_eq!(solve(&parse_input(input, false))?, 123 * 4 + 7 * 8);
+        assert_eq!(part_one_vm(input)?, 123 * 4 + 7 * 8);
+
+        Ok(())
+    }
+
+    /// Corrupted memory built from fragments that are likely to form, or almost form,
+    /// instructions.
+    fn random_memory(rng: &mut StdRng, len: usize) -> String {
+        const FRAGMENTS: [&str; 16] = [
+            "mul(", "mul", "do()", "don't()", "do(", "(", ")", ",", "+", "-", " ", "x", "]", "1",
+            "42", "1234",
+        ];
+        let mut memory = String::new();
+        while memory.len() < len {
+            if rng.random_bool(0.1) {
+                let a = rng.random_range(0..1000);
+                let b = rng.random_range(0..1000);
+                memory.push_str(&format!("mul({a},{b})"));
+            } else {
+                memory.push_str(FRAGMENTS[rng.random_range(0..FRAGMENTS.len())]);
+            }
+        }
+
+        memory
+    }
+
+    #[test]
+    fn test_vm_matches_baseline_random() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..2000 {
+            let len = rng.random_range(0..200);
+            let memory = random_memory(&mut rng, len);
+            assert_eq!(
+                part_one_vm(&memory)?,
+                solve(&parse_input(&memory, false))?,
+                "{memory}"
+            );
+            assert_eq!(
+                part_two_vm(&memory)?,
+                solve(&parse_input(&memory, true))?,
+                "{memory}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_reader_split_calls() -> Result<()> {
+        let set = InstructionSet::part_two();
+        for capacity in 1..12 {
+            let reader = BufReader::with_capacity(capacity, TESTDATA2.as_bytes());
+            assert_eq!(set.run_reader(reader)?, 48);
+        }
+
+        let mut spans = vec![];
+        let reader = BufReader::with_capacity(3, TESTDATA2.as_bytes());
+        set.scan_reader(reader, |call| {
+            spans.push(call.span);
+            Ok(())
+        })?;
+        assert_eq!(
+            spans,
+            set.scan(TESTDATA2.as_bytes())
+                .into_iter()
+                .map(|call| call.span)
+                .collect::<Vec<Range<usize>>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_reader_random() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(41);
+        let set = InstructionSet::part_two();
+        for _ in 0..500 {
+            let len = rng.random_range(0..300);
+            let memory = random_memory(&mut rng, len);
+            let capacity = rng.random_range(1..32);
+            let reader = BufReader::with_capacity(capacity, memory.as_bytes());
+            assert_eq!(
+                set.run_reader(reader)?,
+                set.run(memory.as_bytes())?,
+                "{memory}"
+            );
+        }
+
+        Ok(())
     }
 }
@@ -1,3 +1,5 @@
+use itertools::Itertools;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::collections::HashMap;
 
 const DATA: &str = include_str!("input.txt");
@@ -18,14 +20,43 @@ fn main() {
     let (took, result) = took::took(|| part_two(&input));
     println!("Result part two: {result}");
     println!("Time spent: {took}");
+
+    if std::env::args().any(|arg| arg == "--bench") {
+        benchmark(2000, 2000);
+    }
 }
 
-fn part_one(input: &HashMap<(isize, isize), Letter>) -> usize {
-    input
-        .iter()
-        .filter(|(_, letter)| letter == &&Letter::X)
-        .map(|((x, y), _)| solve_xmas(input, *x, *y))
-        .sum()
+/// Compares the HashMap, dense and bitset searches for XMAS, and the diagonal word and
+/// template searches for the crosses, on a random grid.
+fn benchmark(width: usize, height: usize) {
+    let data = generate(width, height, 2024);
+
+    let (took, input) = took::took(|| parse_map(&data));
+    println!("Time spent parsing {width}x{height} (HashMap): {took}");
+    let (took, result) = took::took(|| find_word(&input, "XMAS", &ONE_DIRECTIONS).len());
+    println!("Result part one (HashMap): {result}");
+    println!("Time spent: {took}");
+
+    let (took, input) = took::took(|| parse(&data));
+    println!("Time spent parsing {width}x{height} (dense): {took}");
+    let (took, result) = took::took(|| find_word(&input, "XMAS", &ONE_DIRECTIONS).len());
+    println!("Result part one (dense): {result}");
+    println!("Time spent: {took}");
+
+    let (took, result) = took::took(|| part_one(&input));
+    println!("Result part one (bitset): {result}");
+    println!("Time spent: {took}");
+
+    let (took, result) = took::took(|| count_crossings(&input));
+    println!("Result part two (diagonal words): {result}");
+    println!("Time spent: {took}");
+    let (took, result) = took::took(|| part_two(&input));
+    println!("Result part two (template): {result}");
+    println!("Time spent: {took}");
+}
+
+fn part_one(input: &Grid) -> usize {
+    count_word(input, "XMAS", &ONE_DIRECTIONS)
 }
 
 const ONE_DIRECTIONS: [(isize, isize); 8] = [
@@ -47,35 +78,19 @@ const ONE_DIRECTIONS: [(isize, isize); 8] = [
     (-1, -1),
 ];
 
-fn solve_xmas(input: &HashMap<(isize, isize), Letter>, x: isize, y: isize) -> usize {
-    ONE_DIRECTIONS
-        .iter()
-        .map(|matrix| solve_xmas_dir(input, x, y, matrix))
-        .sum()
+fn part_two(input: &Grid) -> usize {
+    find_template(input, &Template::new("M.S/.A./M.S")).len()
 }
 
-fn solve_xmas_dir(
-    input: &HashMap<(isize, isize), Letter>,
-    x: isize,
-    y: isize,
-    matrix: &(isize, isize),
-) -> usize {
-    if let Some(Letter::M) = input.get(&(x + matrix.0, y + matrix.1)) {
-        if let Some(Letter::A) = input.get(&(x + matrix.0 * 2, y + matrix.1 * 2)) {
-            if let Some(Letter::S) = input.get(&(x + matrix.0 * 3, y + matrix.1 * 3)) {
-                return 1;
-            }
-        }
-    }
-
-    0
-}
-
-fn part_two(input: &HashMap<(isize, isize), Letter>) -> usize {
-    input
+/// Counts the crosses as diagonal MAS words sharing their A, which the template search
+/// generalises; kept for the benchmark.
+fn count_crossings(input: &impl Letters) -> usize {
+    find_word(input, "MAS", &TWO_DIRECTIONS)
         .iter()
-        .filter(|(_, letter)| letter == &&Letter::A)
-        .filter(|((x, y), _)| solve_mas(input, *x, *y))
+        .map(|m| m.position(1))
+        .counts()
+        .values()
+        .filter(|count| **count > 1)
         .count()
 }
 
@@ -90,60 +105,338 @@ const TWO_DIRECTIONS: [(isize, isize); 4] = [
     (-1, -1),
 ];
 
-fn solve_mas(input: &HashMap<(isize, isize), Letter>, x: isize, y: isize) -> bool {
-    let result: usize = TWO_DIRECTIONS
-        .iter()
-        .map(|matrix| solve_mas_dir(input, x, y, matrix))
-        .sum();
+/// Read access to a grid of letters, so the searches work regardless of the storage.
+trait Letters {
+    fn letter(&self, position: (isize, isize)) -> Option<char>;
+
+    fn letters(&self) -> impl Iterator<Item = ((isize, isize), char)>;
+}
+
+type MapGrid = HashMap<(isize, isize), char>;
 
-    result > 1
+impl Letters for MapGrid {
+    fn letter(&self, position: (isize, isize)) -> Option<char> {
+        self.get(&position).copied()
+    }
+
+    fn letters(&self) -> impl Iterator<Item = ((isize, isize), char)> {
+        self.iter().map(|(position, letter)| (*position, *letter))
+    }
 }
 
-fn solve_mas_dir(
-    input: &HashMap<(isize, isize), Letter>,
+/// The letters stored row by row in a single `Vec`, with bounds-checked lookups.
+struct Grid {
+    width: usize,
+    height: usize,
+    /// `None` past the end of a row shorter than the longest
+    cells: Vec<Option<char>>,
+}
+
+impl Grid {
+    /// Rows shorter than the longest one are padded with cells holding no letter, and a
+    /// grid without any letters is empty.
+    pub fn new(rows: Vec<Vec<char>>) -> Self {
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let height = if width == 0 { 0 } else { rows.len() };
+        let cells = rows
+            .into_iter()
+            .take(height)
+            .flat_map(|row| {
+                let padding = width - row.len();
+                row.into_iter()
+                    .map(Some)
+                    .chain(std::iter::repeat_n(None, padding))
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    pub fn words_per_row(&self) -> usize {
+        self.width.div_ceil(64)
+    }
+
+    /// A bitset per row with the bits set where the row holds `letter`.
+    pub fn bit_rows(&self, letter: char) -> Vec<Vec<u64>> {
+        self.cells
+            .chunks(self.width.max(1))
+            .map(|row| {
+                row.chunks(64)
+                    .map(|chunk| {
+                        chunk
+                            .iter()
+                            .enumerate()
+                            .fold(0, |bits, (x, c)| bits | ((*c == Some(letter)) as u64) << x)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl Letters for Grid {
+    fn letter(&self, (x, y): (isize, isize)) -> Option<char> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+
+        self.cells[y as usize * self.width + x as usize]
+    }
+
+    fn letters(&self) -> impl Iterator<Item = ((isize, isize), char)> {
+        self.cells.iter().enumerate().filter_map(|(i, letter)| {
+            Some((
+                ((i % self.width) as isize, (i / self.width) as isize),
+                (*letter)?,
+            ))
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Match {
     x: isize,
     y: isize,
-    matrix: &(isize, isize),
-) -> usize {
-    if let Some(Letter::M) = input.get(&(x + matrix.0, y + matrix.1)) {
-        if let Some(Letter::S) = input.get(&(x - matrix.0, y - matrix.1)) {
-            return 1;
+    direction: (isize, isize),
+}
+
+impl Match {
+    /// The position of the letter at `index` within the matched word.
+    pub fn position(&self, index: isize) -> (isize, isize) {
+        (
+            self.x + self.direction.0 * index,
+            self.y + self.direction.1 * index,
+        )
+    }
+}
+
+/// Finds every occurrence of `word` reading in any of `directions`, sorted by start
+/// position and direction.
+fn find_word(input: &impl Letters, word: &str, directions: &[(isize, isize)]) -> Vec<Match> {
+    let letters = word.chars().collect::<Vec<char>>();
+    let Some(first) = letters.first() else {
+        return vec![];
+    };
+
+    input
+        .letters()
+        .filter(|(_, letter)| letter == first)
+        .flat_map(|((x, y), _)| {
+            directions.iter().map(move |direction| Match {
+                x,
+                y,
+                direction: *direction,
+            })
+        })
+        .filter(|m| {
+            letters
+                .iter()
+                .enumerate()
+                .skip(1)
+                .all(|(i, letter)| input.letter(m.position(i as isize)) == Some(*letter))
+        })
+        .sorted()
+        .collect()
+}
+
+/// Counts the occurrences of `word` like [`find_word`], but compares whole rows at a
+/// time: every letter of the word gets a bitset per row, and the matches are the AND
+/// of those rows, each shifted by the letter's offset along the direction.
+fn count_word(input: &Grid, word: &str, directions: &[(isize, isize)]) -> usize {
+    let rows = word
+        .chars()
+        .map(|letter| input.bit_rows(letter))
+        .collect::<Vec<Vec<Vec<u64>>>>();
+    if rows.is_empty() {
+        return 0;
+    }
+
+    let height = input.height as isize;
+    let mut shifted = vec![0; input.words_per_row()];
+    let mut count = 0;
+    for (dx, dy) in directions {
+        let last = dy * (rows.len() as isize - 1);
+        for y in 0.max(-last)..height.min(height - last) {
+            let mut found = rows[0][y as usize].clone();
+            for (i, letter_rows) in rows.iter().enumerate().skip(1) {
+                let i = i as isize;
+                shift(&letter_rows[(y + i * dy) as usize], i * dx, &mut shifted);
+                found.iter_mut().zip(&shifted).for_each(|(f, s)| *f &= s);
+            }
+            count += found
+                .iter()
+                .map(|bits| bits.count_ones() as usize)
+                .sum::<usize>();
         }
     }
 
-    0
+    count
 }
 
-#[derive(Debug, PartialEq)]
-enum Letter {
-    X,
-    M,
-    A,
-    S,
+/// Writes `row` into `out` so that bit `x` of `out` holds bit `x + offset` of `row`,
+/// filling in zeroes past either end.
+fn shift(row: &[u64], offset: isize, out: &mut [u64]) {
+    let words = (offset.unsigned_abs() / 64) as isize;
+    let bits = offset.unsigned_abs() % 64;
+    let word = |w: isize| {
+        if w < 0 {
+            0
+        } else {
+            row.get(w as usize).copied().unwrap_or(0)
+        }
+    };
+
+    for (w, bits_out) in out.iter_mut().enumerate() {
+        let w = w as isize;
+        *bits_out = match (offset >= 0, bits) {
+            (true, 0) => word(w + words),
+            (true, _) => word(w + words) >> bits | word(w + words + 1) << (64 - bits),
+            (false, 0) => word(w - words),
+            (false, _) => word(w - words) << bits | word(w - words - 1) >> (64 - bits),
+        };
+    }
 }
 
-impl From<char> for Letter {
-    fn from(c: char) -> Self {
-        match c {
-            'X' => Letter::X,
-            'M' => Letter::M,
-            'A' => Letter::A,
-            'S' => Letter::S,
-            _ => panic!("Unknown letter: {c}"),
+/// A small grid of letters to look for, where `.` matches any letter. Rows are
+/// separated by `/`, e.g. `M.S/.A./M.S` for the X-shaped MAS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Template {
+    cells: Vec<Vec<Option<char>>>,
+}
+
+impl Template {
+    pub fn new(pattern: &str) -> Self {
+        let cells = pattern
+            .split('/')
+            .map(|row| {
+                row.chars()
+                    .map(|c| if c == '.' { None } else { Some(c) })
+                    .collect()
+            })
+            .collect();
+
+        Self { cells }
+    }
+
+    /// Rotates the template a quarter turn clockwise.
+    pub fn rotate(&self) -> Self {
+        let height = self.cells.len();
+        let width = self.cells.iter().map(|row| row.len()).max().unwrap_or(0);
+        let cells = (0..width)
+            .map(|x| {
+                (0..height)
+                    .rev()
+                    .map(|y| self.cells[y].get(x).copied().flatten())
+                    .collect()
+            })
+            .collect();
+
+        Self { cells }
+    }
+
+    /// Mirrors the template left to right.
+    pub fn reflect(&self) -> Self {
+        let width = self.cells.iter().map(|row| row.len()).max().unwrap_or(0);
+        let cells = self
+            .cells
+            .iter()
+            .map(|row| {
+                (0..width)
+                    .rev()
+                    .map(|x| row.get(x).copied().flatten())
+                    .collect()
+            })
+            .collect();
+
+        Self { cells }
+    }
+
+    /// Every distinct rotation and reflection of the template, starting with itself.
+    pub fn variants(&self) -> Vec<Template> {
+        let mut variants: Vec<Template> = vec![];
+        for start in [self.clone(), self.reflect()] {
+            let mut template = start;
+            for _ in 0..4 {
+                let next = template.rotate();
+                if !variants.contains(&template) {
+                    variants.push(template);
+                }
+                template = next;
+            }
         }
+
+        variants
+    }
+
+    pub fn matches_at(&self, input: &impl Letters, x: isize, y: isize) -> bool {
+        self.cells.iter().enumerate().all(|(dy, row)| {
+            row.iter().enumerate().all(|(dx, cell)| {
+                let letter = input.letter((x + dx as isize, y + dy as isize));
+                match cell {
+                    Some(c) => letter == Some(*c),
+                    None => letter.is_some(),
+                }
+            })
+        })
     }
 }
 
-fn parse(input: &str) -> HashMap<(isize, isize), Letter> {
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Placement {
+    x: isize,
+    y: isize,
+    /// Index into [`Template::variants`] of the orientation that matched.
+    variant: usize,
+}
+
+/// Finds every placement of `template` in any rotation or reflection, anchored at the
+/// top left corner of the matching orientation and sorted by position.
+fn find_template(input: &impl Letters, template: &Template) -> Vec<Placement> {
+    let variants = template.variants();
+
+    input
+        .letters()
+        .flat_map(|((x, y), _)| {
+            variants
+                .iter()
+                .enumerate()
+                .filter(move |(_, variant)| variant.matches_at(input, x, y))
+                .map(move |(variant, _)| Placement { x, y, variant })
+        })
+        .sorted()
+        .collect()
+}
+
+/// A random grid of X, M, A and S letters.
+fn generate(width: usize, height: usize, seed: u64) -> String {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..height)
+        .map(|_| {
+            (0..width)
+                .map(|_| ['X', 'M', 'A', 'S'][rng.random_range(0..4)])
+                .collect::<String>()
+        })
+        .join("\n")
+}
+
+fn parse(input: &str) -> Grid {
+    Grid::new(input.lines().map(|line| line.chars().collect()).collect())
+}
+
+fn parse_map(input: &str) -> MapGrid {
     input
         .lines()
         .enumerate()
         .flat_map(|(y, line)| {
             line.chars()
                 .enumerate()
-                .map(move |(x, c)| ((x as isize, y as isize), Letter::from(c)))
+                .map(move |(x, c)| ((x as isize, y as isize), c))
         })
-        .collect::<HashMap<(isize, isize), Letter>>()
+        .collect::<MapGrid>()
 }
 
 #[cfg(test)]
@@ -171,4 +464,172 @@ mod tests {
     fn test_part_two() {
         assert_eq!(part_two(&parse(DATA)), 1850);
     }
+
+    #[test]
+    fn test_find_word_positions() {
+        let grid = parse("CAT.\n.A..\n..TC");
+        assert_eq!(
+            find_word(&grid, "CAT", &ONE_DIRECTIONS),
+            vec![
+                Match {
+                    x: 0,
+                    y: 0,
+                    direction: (1, 0),
+                },
+                Match {
+                    x: 0,
+                    y: 0,
+                    direction: (1, 1),
+                },
+            ]
+        );
+        assert_eq!(
+            find_word(&grid, "TAC", &[(-1, 0)]),
+            vec![Match {
+                x: 2,
+                y: 0,
+                direction: (-1, 0),
+            }]
+        );
+        assert_eq!(find_word(&grid, "TAC", &[(0, 1)]), vec![]);
+        assert_eq!(find_word(&grid, "CT", &[(-1, 0)]).len(), 1);
+    }
+
+    #[test]
+    fn test_find_word_reversed_testdata() {
+        let grid = parse(TESTDATA);
+        assert_eq!(find_word(&grid, "SAMX", &ONE_DIRECTIONS).len(), 18);
+        assert_eq!(find_word(&grid, "XMAS", &[(1, 0), (-1, 0)]).len(), 5);
+    }
+
+    #[test]
+    fn test_find_word_diagonals_testdata() {
+        let grid = parse(TESTDATA);
+        let matches = find_word(&grid, "MAS", &TWO_DIRECTIONS);
+        assert_eq!(matches.len(), 25);
+        assert!(matches
+            .iter()
+            .all(|m| TWO_DIRECTIONS.contains(&m.direction)));
+        assert_eq!(count_crossings(&grid), part_two(&grid));
+        assert_eq!(count_crossings(&parse_map(TESTDATA)), 9);
+    }
+
+    #[test]
+    fn test_template_variants() {
+        assert_eq!(Template::new("M.S/.A./M.S").variants().len(), 4);
+        assert_eq!(Template::new("X./X./XX").variants().len(), 8);
+        assert_eq!(Template::new("XMAS").variants().len(), 4);
+        assert_eq!(Template::new("A.A/.A./A.A").variants().len(), 1);
+        assert_eq!(
+            Template::new("AB/C.").rotate(),
+            Template {
+                cells: vec![vec![Some('C'), Some('A')], vec![None, Some('B')]],
+            }
+        );
+    }
+
+    #[test]
+    fn test_find_template_testdata() {
+        let grid = parse(TESTDATA);
+        assert_eq!(find_template(&grid, &Template::new("XMAS")).len(), 8);
+    }
+
+    #[test]
+    fn test_find_template_shape() {
+        let grid = parse(".L....\n.L..L.\n.LL.L.\n...LL.");
+        assert_eq!(
+            find_template(&grid, &Template::new("L./L./LL")),
+            vec![
+                Placement {
+                    x: 1,
+                    y: 0,
+                    variant: 0,
+                },
+                Placement {
+                    x: 3,
+                    y: 1,
+                    variant: 4,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_storage_agrees_testdata() {
+        let map = parse_map(TESTDATA);
+        let grid = parse(TESTDATA);
+        assert_eq!(
+            find_word(&map, "XMAS", &ONE_DIRECTIONS),
+            find_word(&grid, "XMAS", &ONE_DIRECTIONS)
+        );
+        assert_eq!(
+            find_template(&map, &Template::new("M.S/.A./M.S")),
+            find_template(&grid, &Template::new("M.S/.A./M.S"))
+        );
+    }
+
+    #[test]
+    fn test_ragged_rows() {
+        let input = "XMAS\nXM\n\nSAMXMAS";
+        let grid = parse(input);
+        assert_eq!((grid.width, grid.height), (7, 4));
+        assert_eq!(grid.cells.len(), 28);
+        assert_eq!(grid.letter((2, 1)), None);
+        let map = parse_map(input);
+        assert_eq!(
+            find_word(&grid, "XMAS", &ONE_DIRECTIONS),
+            find_word(&map, "XMAS", &ONE_DIRECTIONS)
+        );
+        assert_eq!(part_one(&grid), 3);
+
+        let input = "AB\nA";
+        let (grid, map) = (parse(input), parse_map(input));
+        let template = Template::new("A./A.");
+        assert_eq!(find_template(&grid, &template), vec![]);
+        assert_eq!(
+            find_template(&grid, &template),
+            find_template(&map, &template)
+        );
+        assert_eq!(find_word(&grid, "B.", &[(0, 1)]), vec![]);
+        assert_eq!(
+            find_word(&grid, "B.", &[(0, 1)]),
+            find_word(&map, "B.", &[(0, 1)])
+        );
+        assert_eq!(
+            count_word(&grid, "B.", &[(0, 1)]),
+            find_word(&grid, "B.", &[(0, 1)]).len()
+        );
+
+        let empty = parse("\n\n");
+        assert_eq!((empty.width, empty.height), (0, 0));
+        assert_eq!(part_one(&empty), 0);
+    }
+
+    #[test]
+    fn test_count_word_generated() {
+        for seed in 0..5 {
+            let grid = parse(&generate(150, 40, seed));
+            for word in ["XMAS", "SAM", "X", "MASXMASXMASXMASXMASXMAS"] {
+                assert_eq!(
+                    count_word(&grid, word, &ONE_DIRECTIONS),
+                    find_word(&grid, word, &ONE_DIRECTIONS).len(),
+                    "{word} with seed {seed}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_shift() {
+        let row = [0b1011, 1 << 63];
+        let mut out = [0; 2];
+        shift(&row, 1, &mut out);
+        assert_eq!(out, [0b101, 1 << 62]);
+        shift(&row, -1, &mut out);
+        assert_eq!(out, [0b10110, 0]);
+        shift(&row, 64, &mut out);
+        assert_eq!(out, [1 << 63, 0]);
+        shift(&row, -65, &mut out);
+        assert_eq!(out, [0, 0b10110]);
+    }
 }
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use nom::{
     bytes::complete::tag,
     character::complete::{self, line_ending},
@@ -7,11 +7,15 @@ use nom::{
     sequence::{pair, separated_pair},
     IResult, Parser,
 };
-use std::collections::HashMap;
+use rand::{rngs::StdRng, seq::IndexedRandom, seq::SliceRandom, Rng, SeedableRng};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter, Write},
+};
 
 const DATA: &str = include_str!("input.txt");
 
-type Rules = HashMap<usize, Vec<usize>>;
+type Pairs = Vec<(usize, usize)>;
 type Pages = Vec<Vec<usize>>;
 
 fn main() -> Result<()> {
@@ -27,91 +31,565 @@ fn main() -> Result<()> {
     println!("Time spent parsing: {took}");
     let (rules, pages) = result?;
 
-    let (took, result) = took::took(|| part_two(&rules, pages));
-    println!("Result part two: {result}");
+    let (took, result) = took::took(|| part_two(&rules, pages.clone()));
+    println!("Result part two: {}", result?);
+    println!("Time spent: {took}");
+
+    let (took, result) = took::took(|| part_two_repair(&rules, &pages));
+    println!("Result part two repair: {}", result?);
     println!("Time spent: {took}");
 
+    if std::env::args().any(|arg| arg == "--bench") {
+        benchmark(1000, 20000, 1000, 101)?;
+    }
+
+    if std::env::args().any(|arg| arg == "--explain") {
+        let (rules, pages) = parse_input(DATA)?;
+        explain(&rules, &pages)?;
+    }
+
+    if std::env::args().any(|arg| arg == "--repair") {
+        print_repairs(&rules, &pages)?;
+    }
+
     Ok(())
 }
 
-fn part_one(rules: &Rules, pages: &Pages) -> usize {
+fn benchmark(pages: usize, rules: usize, updates: usize, len: usize) -> Result<()> {
+    let data = generate(pages, rules, updates, len, 2024);
+    let (_, (pairs, updates)) = parse(&data).map_err(|e| e.to_owned())?;
+    println!(
+        "{} rules, {} updates of {len} pages",
+        pairs.len(),
+        updates.len()
+    );
+
+    let (took, input) = took::took(|| list_rules(&pairs));
+    println!("Time spent indexing (lists): {took}");
+    let (took, result) = took::took(|| part_one(&input, &updates));
+    println!("Result part one (lists): {result}");
+    println!("Time spent: {took}");
+    let (took, result) = took::took(|| sum_corrected_middles(&input, &updates, false));
+    println!("Result part two (lists): {}", result?);
+    println!("Time spent: {took}");
+
+    let (took, input) = took::took(|| Rules::new(&pairs));
+    println!("Time spent indexing (matrix): {took}");
+    let (took, result) = took::took(|| part_one(&input, &updates));
+    println!("Result part one (matrix): {result}");
+    println!("Time spent: {took}");
+    let (took, result) = took::took(|| sum_corrected_middles(&input, &updates, false));
+    println!("Result part two (matrix): {}", result?);
+    println!("Time spent: {took}");
+
+    Ok(())
+}
+
+fn part_one(rules: &impl Precedence, pages: &Pages) -> usize {
     pages
         .iter()
-        .filter_map(|pages| is_valid(rules, pages, true))
+        .filter(|pages| is_valid(rules, pages))
         .map(|v| middle(v))
         .sum()
 }
 
-fn part_two(rules: &Rules, pages: Pages) -> usize {
-    let invalid = pages
+fn part_two(rules: &impl Precedence, pages: Pages) -> Result<usize> {
+    sum_corrected_middles(rules, &pages, true)
+}
+
+/// Sums the middle pages of the invalid updates once sorted. With `strict` an update
+/// whose middle page depends on how pages the rules leave unordered are placed is an
+/// error, rather than counting whichever page the sort happened to put there.
+fn sum_corrected_middles(rules: &impl Precedence, pages: &Pages, strict: bool) -> Result<usize> {
+    pages
         .iter()
-        .filter_map(|pages| is_valid(rules, pages, false))
-        .cloned()
-        .collect::<Vec<Vec<usize>>>();
-
-    invalid
-        .into_iter()
-        .map(|mut pages| {
-            pages.sort_by(comparator(rules));
-            pages
+        .filter(|pages| !is_valid(rules, pages))
+        .map(|pages| {
+            let sorted = topological_order(rules, pages)?;
+            if strict && !sorted.middle_is_fixed(rules) {
+                bail!(
+                    "Middle page of {pages:?} depends on the order of unordered pages {:?}",
+                    sorted.ambiguous
+                );
+            }
+
+            Ok(middle(&sorted.pages))
         })
-        .map(|v| middle(&v))
         .sum()
 }
 
-fn is_valid<'a>(input: &Rules, pages: &'a Vec<usize>, valid: bool) -> Option<&'a Vec<usize>> {
-    for (i, page) in pages.iter().enumerate() {
-        if let Some(rules) = input.get(page) {
-            if pages[..i].iter().any(|b| rules.contains(b)) {
-                return if valid { None } else { Some(pages) };
+fn is_valid(rules: &impl Precedence, pages: &[usize]) -> bool {
+    violations(rules, pages).is_empty()
+}
+
+/// A rule `before|after` broken by an update that has `after` earlier than `before`.
+#[derive(Debug, PartialEq, Eq)]
+struct Violation {
+    before: usize,
+    after: usize,
+    before_position: usize,
+    after_position: usize,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}|{}: {} at position {} comes after {} at position {}",
+            self.before,
+            self.after,
+            self.before,
+            self.before_position,
+            self.after,
+            self.after_position
+        )
+    }
+}
+
+/// Every rule the update breaks, ordered by the position of the page that comes too late.
+fn violations(rules: &impl Precedence, pages: &[usize]) -> Vec<Violation> {
+    let keys = rules.keys(pages);
+    let mut result = vec![];
+    for (i, &page) in keys.iter().enumerate() {
+        for (j, &earlier) in keys[..i].iter().enumerate() {
+            if rules.precedes_key(page, earlier) {
+                result.push(Violation {
+                    before: pages[i],
+                    after: pages[j],
+                    before_position: i,
+                    after_position: j,
+                });
             }
         }
     }
-    if valid {
-        Some(pages)
-    } else {
-        None
+
+    result
+}
+
+/// Prints every invalid update with the rules it breaks and the order that fixes it.
+fn explain(rules: &impl Precedence, pages: &Pages) -> Result<()> {
+    for update in pages {
+        let violations = violations(rules, update);
+        if violations.is_empty() {
+            continue;
+        }
+
+        println!("{update:?}");
+        for violation in violations {
+            println!("  {violation}");
+        }
+        let sorted = topological_order(rules, update)?;
+        println!("  corrected: {:?}", sorted.pages);
+        if !sorted.ambiguous.is_empty() {
+            println!("  unordered: {:?}", sorted.ambiguous);
+            if !sorted.middle_is_fixed(rules) {
+                println!(
+                    "  middle page {} is not fixed by the rules",
+                    middle(&sorted.pages)
+                );
+            }
+        }
     }
+
+    Ok(())
 }
 
 fn middle(v: &[usize]) -> usize {
     *v.get(v.len() / 2).unwrap()
 }
 
-fn comparator(input: &Rules) -> impl Fn(&usize, &usize) -> std::cmp::Ordering + use<'_> {
-    |a, b| {
-        if input.get(a).is_some_and(|p| p.contains(b)) {
-            return std::cmp::Ordering::Less;
+trait Precedence {
+    /// A page as the rules look it up.
+    type Key: Copy;
+
+    fn key(&self, page: usize) -> Self::Key;
+
+    /// Looks up every page of an update once, before comparing them pairwise.
+    fn keys(&self, pages: &[usize]) -> Vec<Self::Key> {
+        pages.iter().map(|&page| self.key(page)).collect()
+    }
+
+    /// Whether a rule puts the page looked up as `a` before the one looked up as `b`.
+    fn precedes_key(&self, a: Self::Key, b: Self::Key) -> bool;
+
+    /// Whether a rule `a|b` puts `a` before `b`.
+    #[cfg(test)]
+    fn precedes(&self, a: usize, b: usize) -> bool {
+        self.precedes_key(self.key(a), self.key(b))
+    }
+}
+
+/// The rules as listed in the input, kept as the baseline for the benchmark.
+type ListRules = HashMap<usize, Vec<usize>>;
+
+impl Precedence for ListRules {
+    type Key = usize;
+
+    fn key(&self, page: usize) -> usize {
+        page
+    }
+
+    fn precedes_key(&self, a: usize, b: usize) -> bool {
+        self.get(&a).is_some_and(|p| p.contains(&b))
+    }
+}
+
+/// The rules as a precedence matrix with a bit per pair of pages. Page numbers are
+/// interned to dense ids, so the matrix is sized to the number of distinct pages; an
+/// update looks its pages up once and then indexes the bits directly.
+struct Rules {
+    ids: HashMap<usize, usize>,
+    /// `u64`s per row of the matrix
+    stride: usize,
+    bits: Vec<u64>,
+}
+
+impl Rules {
+    pub fn new(pairs: &[(usize, usize)]) -> Self {
+        let mut ids = HashMap::new();
+        for &(a, b) in pairs {
+            for page in [a, b] {
+                let id = ids.len();
+                ids.entry(page).or_insert(id);
+            }
         }
-        if input.get(b).is_some_and(|p| p.contains(a)) {
-            return std::cmp::Ordering::Greater;
+
+        let size = ids.len();
+        let stride = size.div_ceil(64);
+        let mut bits = vec![0; size * stride];
+        for (a, b) in pairs {
+            let (a, b) = (ids[a], ids[b]);
+            bits[a * stride + b / 64] |= 1 << (b % 64);
         }
 
-        std::cmp::Ordering::Equal
+        Self { ids, stride, bits }
     }
 }
 
-fn parse_input(input: &'static str) -> Result<(Rules, Pages)> {
-    let (_, input) = parse(input)?;
+impl Precedence for Rules {
+    /// The page's row and column in the matrix, if any rule mentions it
+    type Key = Option<usize>;
+
+    fn key(&self, page: usize) -> Option<usize> {
+        self.ids.get(&page).copied()
+    }
+
+    fn precedes_key(&self, a: Option<usize>, b: Option<usize>) -> bool {
+        let (Some(a), Some(b)) = (a, b) else {
+            return false;
+        };
+
+        self.bits[a * self.stride + b / 64] >> (b % 64) & 1 == 1
+    }
+}
+
+/// An update put in an order satisfying every rule between its pages.
+#[derive(Debug)]
+struct Sorted {
+    pages: Vec<usize>,
+    /// Pairs of pages the rules leave unordered, so another valid order would swap them.
+    ambiguous: Vec<(usize, usize)>,
+}
+
+impl Sorted {
+    /// Whether every valid order has the same middle page, i.e. the rules between the
+    /// pages put everything before the middle one ahead of it and everything after it
+    /// behind it.
+    pub fn middle_is_fixed(&self, rules: &impl Precedence) -> bool {
+        if self.ambiguous.is_empty() {
+            return true;
+        }
+
+        let reach = reachability(rules, &self.pages);
+        let mid = self.pages.len() / 2;
+        (0..mid).all(|i| reach[i][mid]) && (mid + 1..self.pages.len()).all(|j| reach[mid][j])
+    }
+}
+
+/// Sorts the pages topologically over the rules between them (Kahn's algorithm). When
+/// several pages are free to go next the earliest in the update is taken and the pair
+/// reported as ambiguous; rules forming a cycle are an error.
+fn topological_order(rules: &impl Precedence, pages: &[usize]) -> Result<Sorted> {
+    let len = pages.len();
+    let keys = rules.keys(pages);
+    let mut incoming = vec![0; len];
+    for &a in &keys {
+        for (j, &b) in keys.iter().enumerate() {
+            if rules.precedes_key(a, b) {
+                incoming[j] += 1;
+            }
+        }
+    }
+
+    let mut placed = vec![false; len];
+    let mut sorted = Sorted {
+        pages: Vec::with_capacity(len),
+        ambiguous: vec![],
+    };
+    while sorted.pages.len() < len {
+        let mut ready = (0..len).filter(|&i| !placed[i] && incoming[i] == 0);
+        let Some(next) = ready.next() else {
+            let cycle = find_cycle(rules, pages, &placed);
+            bail!(
+                "Rules form a cycle: {}",
+                cycle
+                    .iter()
+                    .map(|page| page.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" -> ")
+            );
+        };
+        if let Some(other) = ready.next() {
+            sorted.ambiguous.push((pages[next], pages[other]));
+        }
 
-    Ok(input)
+        placed[next] = true;
+        sorted.pages.push(pages[next]);
+        for (j, &key) in keys.iter().enumerate() {
+            if !placed[j] && rules.precedes_key(keys[next], key) {
+                incoming[j] -= 1;
+            }
+        }
+    }
+
+    Ok(sorted)
+}
+
+/// Every page left unplaced by the topological sort has an unplaced predecessor, so
+/// walking back through them must come round to a page already seen. Returns that loop
+/// in rule order, with its first page repeated at the end.
+fn find_cycle(rules: &impl Precedence, pages: &[usize], placed: &[bool]) -> Vec<usize> {
+    let keys = rules.keys(pages);
+    let mut current = (0..pages.len()).find(|&i| !placed[i]).unwrap();
+    let mut path = vec![];
+    while !path.contains(&current) {
+        path.push(current);
+        current = (0..pages.len())
+            .find(|&i| !placed[i] && rules.precedes_key(keys[i], keys[current]))
+            .unwrap();
+    }
+
+    let start = path.iter().position(|i| *i == current).unwrap();
+    let mut cycle = path[start..]
+        .iter()
+        .rev()
+        .map(|i| pages[*i])
+        .collect::<Vec<usize>>();
+    cycle.push(cycle[0]);
+
+    cycle
 }
 
-fn parse(input: &str) -> IResult<&str, (Rules, Pages)> {
-    separated_pair(parse_rules, pair(line_ending, line_ending), parse_pages).parse(input)
+/// A page taken out of an update and put back elsewhere.
+#[derive(Debug, PartialEq, Eq)]
+struct Move {
+    page: usize,
+    /// Position in the original update
+    from: usize,
+    /// Position in the repaired update
+    to: usize,
 }
 
-fn parse_rules(input: &str) -> IResult<&str, Rules> {
-    map(separated_list1(line_ending, parse_rule), |v| {
-        let mut map = Rules::new();
-        for (a, b) in v {
-            map.entry(a).or_default().push(b);
+/// An update made valid by moving as few pages as possible.
+#[derive(Debug)]
+struct Repair {
+    pages: Vec<usize>,
+    moves: Vec<Move>,
+}
+
+impl Repair {
+    pub fn middle(&self) -> usize {
+        middle(&self.pages)
+    }
+}
+
+/// Repairs the update with the fewest moves. Pages left in place must not be ordered the
+/// other way round by the rules, directly or through other pages of the update, so with
+/// `a ≺ b` meaning "`a` comes before `b` but the rules want `b` before `a`", which is a
+/// partial order, the pages to keep are its largest antichain. By Dilworth's theorem that
+/// follows from a maximum matching between the `a`s and `b`s, recovered through König's
+/// theorem. The moved pages are then slotted in by a topological sort that keeps the
+/// others in their original order.
+fn repair(rules: &impl Precedence, pages: &[usize]) -> Result<Repair> {
+    topological_order(rules, pages)?;
+
+    let reach = reachability(rules, pages);
+    let len = pages.len();
+    let inverted = |i: usize, j: usize| i < j && reach[j][i];
+
+    // maximum matching from the left copy of every position to the right copy
+    let mut matched = vec![None; len];
+    for i in 0..len {
+        augment(i, &inverted, &mut vec![false; len], &mut matched);
+    }
+
+    // König: alternating paths from the unmatched left positions
+    let mut left = vec![false; len];
+    let mut right = vec![false; len];
+    let mut stack = (0..len)
+        .filter(|i| !matched.contains(&Some(*i)))
+        .collect::<Vec<usize>>();
+    while let Some(i) = stack.pop() {
+        if left[i] {
+            continue;
         }
-        map
+        left[i] = true;
+        for j in 0..len {
+            if !inverted(i, j) || right[j] {
+                continue;
+            }
+            right[j] = true;
+            if let Some(k) = matched[j] {
+                stack.push(k);
+            }
+        }
+    }
+    let kept = (0..len)
+        .map(|i| left[i] && !right[i])
+        .collect::<Vec<bool>>();
+
+    // the kept pages are chained in their original order on top of the rules
+    let mut placed = vec![false; len];
+    let mut order = Vec::with_capacity(len);
+    while order.len() < len {
+        let next = (0..len)
+            .find(|&j| {
+                !placed[j]
+                    && (0..len)
+                        .all(|i| placed[i] || !(reach[i][j] || (kept[i] && kept[j] && i < j)))
+            })
+            .unwrap();
+        placed[next] = true;
+        order.push(next);
+    }
+
+    let moves = order
+        .iter()
+        .enumerate()
+        .filter(|(_, i)| !kept[**i])
+        .map(|(to, &from)| Move {
+            page: pages[from],
+            from,
+            to,
+        })
+        .collect();
+
+    Ok(Repair {
+        pages: order.into_iter().map(|i| pages[i]).collect(),
+        moves,
     })
+}
+
+/// Kuhn's augmenting path search, `matched[j]` being the left position matched to `j`.
+fn augment(
+    i: usize,
+    edge: &impl Fn(usize, usize) -> bool,
+    seen: &mut [bool],
+    matched: &mut [Option<usize>],
+) -> bool {
+    for j in 0..matched.len() {
+        if edge(i, j) && !seen[j] {
+            seen[j] = true;
+            if matched[j].is_none_or(|k| augment(k, edge, seen, matched)) {
+                matched[j] = Some(i);
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+/// `reach[i][j]` is whether the rules between the pages of the update put `pages[i]`
+/// before `pages[j]`, directly or through other pages (Floyd–Warshall).
+fn reachability(rules: &impl Precedence, pages: &[usize]) -> Vec<Vec<bool>> {
+    let keys = rules.keys(pages);
+    let mut reach = keys
+        .iter()
+        .map(|&a| keys.iter().map(|&b| rules.precedes_key(a, b)).collect())
+        .collect::<Vec<Vec<bool>>>();
+    for k in 0..pages.len() {
+        let through = reach[k].clone();
+        for row in reach.iter_mut().filter(|row| row[k]) {
+            for (cell, next) in row.iter_mut().zip(&through) {
+                *cell |= next;
+            }
+        }
+    }
+
+    reach
+}
+
+fn part_two_repair(rules: &impl Precedence, pages: &Pages) -> Result<usize> {
+    pages
+        .iter()
+        .filter(|pages| !is_valid(rules, pages))
+        .map(|pages| repair(rules, pages).map(|repair| repair.middle()))
+        .sum()
+}
+
+/// Prints the moves repairing every invalid update.
+fn print_repairs(rules: &impl Precedence, pages: &Pages) -> Result<()> {
+    for update in pages.iter().filter(|pages| !is_valid(rules, pages)) {
+        let repair = repair(rules, update)?;
+        println!("{update:?} -> {:?}", repair.pages);
+        for m in &repair.moves {
+            println!("  move {} from position {} to {}", m.page, m.from, m.to);
+        }
+        println!("  middle page: {}", repair.middle());
+    }
+
+    Ok(())
+}
+
+/// Rules sampled from one random order of the pages, so they never form a cycle, and
+/// updates of `len` distinct pages in random order.
+fn generate(pages: usize, rules: usize, updates: usize, len: usize, seed: u64) -> String {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut order = (0..pages).collect::<Vec<usize>>();
+    order.shuffle(&mut rng);
+
+    let mut data = String::new();
+    for _ in 0..rules {
+        let a = rng.random_range(0..pages - 1);
+        let b = rng.random_range(a + 1..pages);
+        writeln!(data, "{}|{}", order[a], order[b]).unwrap();
+    }
+    for _ in 0..updates {
+        data.push('\n');
+        let update = order
+            .choose_multiple(&mut rng, len)
+            .map(|page| page.to_string());
+        data.push_str(&update.collect::<Vec<String>>().join(","));
+    }
+
+    data
+}
+
+fn parse_input(input: &'static str) -> Result<(Rules, Pages)> {
+    let (_, (rules, pages)) = parse(input)?;
+
+    Ok((Rules::new(&rules), pages))
+}
+
+fn parse(input: &str) -> IResult<&str, (Pairs, Pages)> {
+    separated_pair(
+        separated_list1(line_ending, parse_rule),
+        pair(line_ending, line_ending),
+        parse_pages,
+    )
     .parse(input)
 }
 
+fn list_rules(pairs: &[(usize, usize)]) -> ListRules {
+    let mut map = ListRules::new();
+    for &(a, b) in pairs {
+        map.entry(a).or_default().push(b);
+    }
+
+    map
+}
+
 fn parse_rule(input: &str) -> IResult<&str, (usize, usize)> {
     map(
         separated_pair(complete::u32, tag("|"), complete::u32),
@@ -131,6 +609,7 @@ fn parse_page(input: &str) -> IResult<&str, Vec<usize>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::seq::SliceRandom;
 
     const TESTDATA: &str = include_str!("test.txt");
 
@@ -153,7 +632,7 @@ mod tests {
     #[test]
     fn test_part_two_testdata() -> Result<()> {
         let (rules, pages) = parse_input(TESTDATA)?;
-        assert_eq!(part_two(&rules, pages), 123);
+        assert_eq!(part_two(&rules, pages)?, 123);
 
         Ok(())
     }
@@ -161,7 +640,239 @@ mod tests {
     #[test]
     fn test_part_two() -> Result<()> {
         let (rules, pages) = parse_input(DATA)?;
-        assert_eq!(part_two(&rules, pages), 4260);
+        assert_eq!(part_two(&rules, pages)?, 4260);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_topological_order_incomplete_rules() -> Result<()> {
+        let rules = Rules::new(&[(1, 2), (3, 4), (2, 4)]);
+        let sorted = topological_order(&rules, &[4, 3, 2, 1])?;
+        assert_eq!(sorted.pages, vec![3, 1, 2, 4]);
+        assert_eq!(sorted.ambiguous, vec![(3, 1)]);
+        assert!(is_valid(&rules, &sorted.pages));
+        assert!(!sorted.middle_is_fixed(&rules));
+        assert_eq!(
+            part_two(&rules, vec![vec![4, 3, 2, 1]])
+                .unwrap_err()
+                .to_string(),
+            "Middle page of [4, 3, 2, 1] depends on the order of unordered pages [(3, 1)]"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two_ambiguous_tail() -> Result<()> {
+        let rules = Rules::new(&[(1, 2), (2, 3), (3, 4), (3, 5)]);
+        let sorted = topological_order(&rules, &[2, 1, 3, 5, 4])?;
+        assert_eq!(sorted.ambiguous, vec![(5, 4)]);
+        assert!(sorted.middle_is_fixed(&rules));
+        assert_eq!(part_two(&rules, vec![vec![2, 1, 3, 5, 4]])?, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_topological_order_cycle() {
+        let rules = Rules::new(&[(1, 2), (2, 3), (3, 1), (4, 1)]);
+        assert_eq!(
+            topological_order(&rules, &[4, 1, 2, 3])
+                .unwrap_err()
+                .to_string(),
+            "Rules form a cycle: 2 -> 3 -> 1 -> 2"
+        );
+        assert!(topological_order(&rules, &[4, 1, 2]).is_ok());
+    }
+
+    #[test]
+    fn test_topological_order_random() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..200 {
+            // rules sampled from one hidden order are consistent, but rarely complete
+            let mut order = (10..60).collect::<Vec<usize>>();
+            order.shuffle(&mut rng);
+            let mut pairs = vec![];
+            for i in 0..order.len() {
+                for j in i + 1..order.len() {
+                    if rng.random_bool(0.3) {
+                        pairs.push((order[i], order[j]));
+                    }
+                }
+            }
+            let rules = Rules::new(&pairs);
+
+            let mut update = order[..rng.random_range(1..order.len())].to_vec();
+            update.shuffle(&mut rng);
+            let sorted = topological_order(&rules, &update)?;
+            assert!(is_valid(&rules, &sorted.pages));
+            for (a, b) in sorted.ambiguous {
+                assert!(!rules.precedes(a, b) && !rules.precedes(b, a));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_violations_testdata() -> Result<()> {
+        let (rules, pages) = parse_input(TESTDATA)?;
+        assert!(violations(&rules, &pages[0]).is_empty());
+        assert_eq!(
+            violations(&rules, &pages[3]),
+            vec![Violation {
+                before: 97,
+                after: 75,
+                before_position: 1,
+                after_position: 0,
+            }]
+        );
+        assert_eq!(
+            violations(&rules, &pages[5])
+                .iter()
+                .map(|violation| violation.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "75|13: 75 at position 2 comes after 13 at position 1",
+                "29|13: 29 at position 3 comes after 13 at position 1",
+                "47|13: 47 at position 4 comes after 13 at position 1",
+                "47|29: 47 at position 4 comes after 29 at position 3",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_matches_lists_random() {
+        let mut rng = StdRng::seed_from_u64(44);
+        let pairs = (0..2000)
+            .map(|_| (rng.random_range(0..300), rng.random_range(0..300)))
+            .collect::<Pairs>();
+        let matrix = Rules::new(&pairs);
+        let lists = list_rules(&pairs);
+        for a in 0..310 {
+            for b in 0..310 {
+                assert_eq!(matrix.precedes(a, b), lists.precedes(a, b), "{a}|{b}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_matrix_sparse_ids() {
+        let pairs = [(1_000_000_007, 42), (42, usize::MAX), (7, 1_000_000_007)];
+        let rules = Rules::new(&pairs);
+        assert_eq!(rules.ids.len(), 4);
+        assert_eq!(rules.bits.len(), 4);
+        for &(a, b) in &pairs {
+            assert!(rules.precedes(a, b), "{a}|{b}");
+            assert!(!rules.precedes(b, a), "{b}|{a}");
+        }
+        assert!(!rules.precedes(7, 42));
+        assert!(!rules.precedes(8, 42));
+    }
+
+    #[test]
+    fn test_generate() -> Result<()> {
+        let data = generate(200, 1000, 50, 21, 1);
+        let (_, (pairs, updates)) = parse(&data).map_err(|e| e.to_owned())?;
+        assert_eq!(pairs.len(), 1000);
+        assert_eq!(updates.len(), 50);
+        assert!(updates.iter().all(|update| update.len() == 21));
+
+        let rules = Rules::new(&pairs);
+        assert_eq!(
+            sum_corrected_middles(&rules, &updates, false)?,
+            sum_corrected_middles(&list_rules(&pairs), &updates, false)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_two_repair_testdata() -> Result<()> {
+        let (rules, pages) = parse_input(TESTDATA)?;
+        assert_eq!(part_two_repair(&rules, &pages)?, 123);
+
+        let repaired = repair(&rules, &pages[3])?;
+        assert_eq!(repaired.pages, vec![97, 75, 47, 61, 53]);
+        assert_eq!(
+            repaired.moves,
+            vec![Move {
+                page: 75,
+                from: 0,
+                to: 1,
+            }]
+        );
+        assert_eq!(repair(&rules, &pages[5])?.moves.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_repair_through_transitive_rules() -> Result<()> {
+        // 1 and 3 are unrelated by any single rule, yet 3 must come after 1
+        let rules = Rules::new(&[(1, 2), (2, 3)]);
+        let repaired = repair(&rules, &[3, 1, 2])?;
+        assert_eq!(repaired.pages, vec![1, 2, 3]);
+        assert_eq!(repaired.moves.len(), 1);
+
+        Ok(())
+    }
+
+    /// Fewest moves found by trying every set of pages to keep in place.
+    fn fewest_moves_brute_force(rules: &Rules, pages: &[usize]) -> usize {
+        let reach = reachability(rules, pages);
+        let len = pages.len();
+        (0..1u32 << len)
+            .filter(|kept| {
+                (0..len).all(|i| {
+                    (i + 1..len).all(|j| kept >> i & 1 == 0 || kept >> j & 1 == 0 || !reach[j][i])
+                })
+            })
+            .map(|kept| len - kept.count_ones() as usize)
+            .min()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_repair_random() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(45);
+        for _ in 0..300 {
+            let data = generate(
+                12,
+                rng.random_range(1..30),
+                1,
+                rng.random_range(1..9),
+                rng.random(),
+            );
+            let (_, (pairs, updates)) = parse(&data).map_err(|e| e.to_owned())?;
+            let rules = Rules::new(&pairs);
+            let update = &updates[0];
+
+            let repaired = repair(&rules, update)?;
+            assert!(is_valid(&rules, &repaired.pages), "{update:?}");
+            assert_eq!(
+                repaired.moves.len(),
+                fewest_moves_brute_force(&rules, update),
+                "{update:?}"
+            );
+
+            let moved = repaired
+                .moves
+                .iter()
+                .map(|m| m.page)
+                .collect::<Vec<usize>>();
+            let unmoved = |pages: &[usize]| {
+                pages
+                    .iter()
+                    .filter(|page| !moved.contains(page))
+                    .copied()
+                    .collect::<Vec<usize>>()
+            };
+            assert_eq!(unmoved(update), unmoved(&repaired.pages));
+        }
 
         Ok(())
     }
@@ -1,5 +1,7 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rayon::prelude::*;
+use std::{collections::HashMap, str::FromStr};
 
 const DATA: &str = include_str!("input.txt");
 
@@ -16,84 +18,512 @@ fn main() -> Result<()> {
     println!("Time spent parsing: {took}");
     let (start, grid) = result?;
 
-    let (took, result) = took::took(|| part_two(start, &grid));
+    let (took, result) = took::took(|| part_two(start.clone(), &grid));
     println!("Result part two: {result}");
     println!("Time spent: {took}");
 
+    if std::env::args().any(|arg| arg == "--bench") {
+        benchmark(130, 0.04)?;
+        benchmark(1000, 0.01)?;
+    }
+
+    if let Some(rules) = std::env::args().skip_while(|arg| arg != "--rules").nth(1) {
+        let rules = rules.parse::<Rules>()?;
+        let (took, (part_one, part_two)) = took::took(|| solve_with_rules(&start, &grid, rules));
+        println!("Result part one with {rules:?}: {part_one}");
+        println!("Result part two with {rules:?}: {part_two}");
+        println!("Time spent: {took}");
+    }
+
+    if std::env::args().any(|arg| arg == "--traps") {
+        println!(
+            "Without obstruction: {:?}",
+            GuardWalk::new(start.clone(), &grid).finish()
+        );
+        for trap in traps(&start, &grid) {
+            let cycle = &trap.cycle;
+            println!(
+                "{:?}: loop of {} steps from {:?}",
+                trap.block, cycle.len, cycle.start
+            );
+        }
+    }
+
+    if let Some(block) = std::env::args().skip_while(|arg| arg != "--render").nth(1) {
+        let block = block
+            .split_once(',')
+            .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)))
+            .ok_or_else(|| anyhow!("Expected x,y after --render, found {block}"))?;
+        match traps(&start, &grid)
+            .into_iter()
+            .find(|trap| trap.block == block)
+        {
+            Some(trap) => println!("{}", render(&start, &grid, &trap)),
+            None => bail!("An obstruction at {block:?} does not trap the guard"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Compares finding the traps from turn to turn over the jump table with walking every
+/// candidate obstruction cell by cell, on random maps. The table is built once per map,
+/// so the gap grows with the length of the walk.
+fn benchmark(size: usize, density: f64) -> Result<()> {
+    let data = generate(size, density, 2024);
+    let (start, grid) = parse_input(&data)?;
+
+    let (took, result) = took::took(|| part_one(start.clone(), &grid));
+    println!("Result part one ({size}x{size}): {result}");
+    println!("Time spent: {took}");
+
+    let (took, (_, result)) = took::took(|| solve_with_rules(&start, &grid, Rules::default()));
+    println!("Result part two (cell by cell): {result}");
+    println!("Time spent: {took}");
+
+    let (took, result) = took::took(|| part_two(start.clone(), &grid));
+    println!("Result part two (jumps): {result}");
+    println!("Time spent: {took}");
+
     Ok(())
 }
 
 fn part_one(start: State, grid: &[Vec<bool>]) -> usize {
-    walked_positions(start, grid).len()
+    walked_positions(start, grid, Rules::default()).len()
+}
+
+/// The distinct positions the guard walks through, in the order it first gets there.
+fn walked_positions(start: State, grid: &[Vec<bool>], rules: Rules) -> Vec<(usize, usize)> {
+    let mut visited = vec![vec![false; grid[0].len()]; grid.len()];
+    GuardWalk::new(start, grid)
+        .with_rules(rules)
+        .filter(|state| !std::mem::replace(&mut visited[state.y][state.x], true))
+        .map(|state| (state.x, state.y))
+        .collect()
+}
+
+/// How a walk ends.
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    /// The guard walked off the map from this state.
+    Exited(State),
+    /// The guard got back to `cycle_start`, the first state of the walk on the loop, after
+    /// `cycle_len` more steps.
+    Looped {
+        cycle_start: State,
+        cycle_len: usize,
+    },
 }
 
-fn walked_positions(start: State, grid: &[Vec<bool>]) -> Vec<(usize, usize)> {
-    let mut state = start;
-    let bounds = (grid[0].len(), grid.len());
-    let mut set = Vec::new();
-    set.push((state.x, state.y));
-    while let Some(new_state) = step(&state, grid, &bounds) {
-        state = new_state;
-        set.push((state.x, state.y));
+/// The guard's walk one step at a time, where a step either moves the guard forward or
+/// turns it in place. Yields the start and every state after it, then [`GuardWalk::outcome`]
+/// tells whether the guard walked off the map or got stuck in a loop.
+struct GuardWalk<'a> {
+    grid: &'a [Vec<bool>],
+    block: Option<(usize, usize)>,
+    rules: Rules,
+    /// Which way the next turn goes
+    side: Side,
+    start: State,
+    next: Option<State>,
+    /// Whether the guard turned or wrapped round the map to get to `next`. A loop has to
+    /// do one or the other somewhere, so only those states are remembered.
+    turned: bool,
+    steps: usize,
+    /// Step at which every state reached by turning or wrapping was reached, with the side
+    /// of the turn to come since alternating turns make the same state lead on differently
+    seen: HashMap<(State, Side), usize>,
+    outcome: Option<Outcome>,
+}
+
+impl<'a> GuardWalk<'a> {
+    pub fn new(start: State, grid: &'a [Vec<bool>]) -> Self {
+        Self {
+            grid,
+            block: None,
+            rules: Rules::default(),
+            side: Side::Right,
+            start: start.clone(),
+            next: Some(start),
+            turned: false,
+            steps: 0,
+            seen: HashMap::new(),
+            outcome: None,
+        }
+    }
+
+    /// Adds an obstruction at `block` on top of the grid.
+    pub fn with_block(mut self, block: (usize, usize)) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self.side = self.first_side();
+        self
     }
 
-    set.sort();
-    set.dedup();
-    set
+    fn first_side(&self) -> Side {
+        match self.rules.turning {
+            Turning::Right | Turning::Alternating => Side::Right,
+            Turning::Left => Side::Left,
+        }
+    }
+
+    /// How the walk ended, once the iterator is exhausted.
+    pub fn outcome(&self) -> Option<&Outcome> {
+        self.outcome.as_ref()
+    }
+
+    /// Walks to the end and returns how it ended.
+    pub fn finish(mut self) -> Outcome {
+        self.by_ref().for_each(drop);
+        self.outcome.unwrap()
+    }
+
+    fn is_blocked(&self, x: usize, y: usize) -> bool {
+        self.grid[y][x] || self.block == Some((x, y))
+    }
+
+    /// The state after `state` with the side of the turn after that, and whether the guard
+    /// turned or wrapped round the map to get there, or `None` if it walks off the map.
+    fn step(&self, state: &State, side: Side) -> Option<(State, Side, bool)> {
+        let bounds = (self.grid[0].len(), self.grid.len());
+        let next = state.advance(&bounds, self.rules.wrap)?;
+        if self.is_blocked(next.x, next.y) {
+            let direction = state.direction.turn(side, self.rules.diagonal);
+            let side = match self.rules.turning {
+                Turning::Alternating => side.other(),
+                _ => side,
+            };
+            return Some((State::new(state.x, state.y, direction), side, true));
+        }
+
+        let (dx, dy) = state.direction.delta();
+        let wrapped =
+            next.x as isize != state.x as isize + dx || next.y as isize != state.y as isize + dy;
+        Some((next, side, wrapped))
+    }
+
+    /// How the walk ended, once it is found to go round a loop of `cycle_len` steps. A
+    /// second guard walking from the start `cycle_len` steps ahead first meets this one
+    /// where the walk joins the loop.
+    fn looped(&self, cycle_len: usize) -> Outcome {
+        let step = |(state, side): (State, Side)| {
+            let (state, side, _) = self
+                .step(&state, side)
+                .expect("a guard going round a loop never walks off the map");
+            (state, side)
+        };
+        let mut behind = (self.start.clone(), self.first_side());
+        let mut ahead = (0..cycle_len).fold(behind.clone(), |guard, _| step(guard));
+        while behind != ahead {
+            behind = step(behind);
+            ahead = step(ahead);
+        }
+
+        Outcome::Looped {
+            cycle_start: behind.0,
+            cycle_len,
+        }
+    }
 }
 
-fn step(state: &State, grid: &[Vec<bool>], bounds: &(usize, usize)) -> Option<State> {
-    match state.make_move(bounds) {
-        None => None,
-        Some(new_state) => {
-            if grid[new_state.y][new_state.x] {
-                let right_state = state.turn_right();
-                Some(right_state)
-            } else {
-                Some(new_state)
+impl Iterator for GuardWalk<'_> {
+    type Item = State;
+
+    fn next(&mut self) -> Option<State> {
+        let state = self.next.take()?;
+        if self.turned {
+            let key = (state.clone(), self.side);
+            if let Some(&step) = self.seen.get(&key) {
+                self.outcome = Some(self.looped(self.steps - step));
+                return None;
+            }
+            self.seen.insert(key, self.steps);
+        }
+
+        self.steps += 1;
+        match self.step(&state, self.side) {
+            None => self.outcome = Some(Outcome::Exited(state.clone())),
+            Some((next, side, turned)) => {
+                self.next = Some(next);
+                self.side = side;
+                self.turned = turned;
             }
         }
+
+        Some(state)
     }
 }
 
 fn part_two(start: State, grid: &[Vec<bool>]) -> usize {
-    let bounds = (grid[0].len(), grid.len());
+    traps(&start, grid).len()
+}
 
-    walked_positions(start.clone(), grid)
+/// Answers both parts under other rules. The jump table only knows the puzzle's rules,
+/// so every candidate obstruction is walked cell by cell. Wrapping round the edges the
+/// guard can never leave, so then every obstruction counts as a loop.
+fn solve_with_rules(start: &State, grid: &[Vec<bool>], rules: Rules) -> (usize, usize) {
+    let walked = walked_positions(start.clone(), grid, rules);
+    let loops = walked
         .par_iter()
-        .map(|block| {
-            let mut state = start.clone();
-            let mut visited = vec![];
-            while let Some(new_state) = step_block(&state, grid, &bounds, block) {
-                if new_state.direction != state.direction {
-                    if visited.contains(&new_state) {
-                        return 1;
+        .filter(|block| {
+            let walk = GuardWalk::new(start.clone(), grid)
+                .with_rules(rules)
+                .with_block(**block);
+            matches!(walk.finish(), Outcome::Looped { .. })
+        })
+        .count();
+
+    (walked.len(), loops)
+}
+
+/// An obstruction that traps the guard, with the loop it gets stuck in.
+#[derive(Debug)]
+struct Trap {
+    block: (usize, usize),
+    cycle: Cycle,
+}
+
+/// A loop in the guard's walk.
+#[derive(Debug, PartialEq, Eq)]
+struct Cycle {
+    /// The first state of the walk that is part of the loop
+    start: State,
+    /// Steps round the loop, counting both moves and turns
+    len: usize,
+}
+
+/// Every position on the guard's walk where an obstruction gets it stuck in a loop.
+fn traps(start: &State, grid: &[Vec<bool>]) -> Vec<Trap> {
+    let jumps = Jumps::new(grid);
+
+    walked_positions(start.clone(), grid, Rules::default())
+        .par_iter()
+        .map_init(
+            || Turns::new(jumps.width * jumps.height * 4),
+            |turns, block| {
+                jumps.find_loop(start, *block, turns).map(|cycle| Trap {
+                    block: *block,
+                    cycle,
+                })
+            },
+        )
+        .flatten()
+        .collect()
+}
+
+/// Draws the map with the walk without obstruction as `X`, the obstruction as `O` and the
+/// loop the guard is then stuck in as `|` and `-`, with `+` where it turns or crosses.
+fn render(start: &State, grid: &[Vec<bool>], trap: &Trap) -> String {
+    let mut canvas = grid
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|obstacle| if *obstacle { '#' } else { '.' })
+                .collect()
+        })
+        .collect::<Vec<Vec<char>>>();
+    for (x, y) in walked_positions(start.clone(), grid, Rules::default()) {
+        canvas[y][x] = 'X';
+    }
+
+    let mut walk = GuardWalk::new(start.clone(), grid).with_block(trap.block);
+    let states = walk.by_ref().collect::<Vec<State>>();
+    let Some(Outcome::Looped { cycle_len, .. }) = walk.outcome() else {
+        unreachable!("a trap always ends the walk in a loop");
+    };
+    let cycle = &states[states.len() - cycle_len..];
+    for (previous, state) in cycle.iter().cycle().skip(cycle.len() - 1).zip(cycle) {
+        let mark = match state.direction {
+            _ if previous.direction != state.direction => '+',
+            Direction::Up | Direction::Down => '|',
+            Direction::Right | Direction::Left => '-',
+            direction => direction.symbol(),
+        };
+        let cell = &mut canvas[state.y][state.x];
+        *cell = match (*cell, mark) {
+            ('|', '-') | ('-', '|') | ('+', _) => '+',
+            _ => mark,
+        };
+    }
+    canvas[trap.block.1][trap.block.0] = 'O';
+    canvas[start.y][start.x] = start.direction.symbol();
+
+    canvas
+        .into_iter()
+        .map(|row| row.into_iter().collect::<String>())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// For every cell and direction, the cell where a guard walking from there stops in front
+/// of the next obstacle, or `None` if it walks off the map. Lets the simulation go from
+/// turn to turn instead of cell by cell.
+struct Jumps {
+    width: usize,
+    height: usize,
+    /// The cell index of every stop, `OFF_MAP` where there is none
+    stops: Vec<[u32; 4]>,
+}
+
+const OFF_MAP: u32 = u32::MAX;
+
+impl Jumps {
+    pub fn new(grid: &[Vec<bool>]) -> Self {
+        let (width, height) = (grid[0].len(), grid.len());
+        let mut stops = vec![[OFF_MAP; 4]; width * height];
+        // the cell ahead has to be done first, so sweep against the direction
+        let sweep = |len: usize, delta: isize| {
+            (0..len).map(move |i| if delta > 0 { len - 1 - i } else { i })
+        };
+        for direction in DIRECTIONS {
+            let (dx, dy) = direction.delta();
+            let d = direction.index();
+            // the stop of the cell done last in every column, going up or down
+            let mut ahead = vec![OFF_MAP; width];
+            for y in sweep(height, dy) {
+                let row = &mut stops[y * width..(y + 1) * width];
+                if dy == 0 {
+                    let mut stop = OFF_MAP;
+                    for x in sweep(width, dx) {
+                        if grid[y].get(x.wrapping_add_signed(dx)) == Some(&true) {
+                            stop = (y * width + x) as u32;
+                        }
+                        row[x][d] = stop;
+                    }
+                } else if let Some(next_row) = grid.get(y.wrapping_add_signed(dy)) {
+                    for (x, cell) in row.iter_mut().enumerate() {
+                        if next_row[x] {
+                            ahead[x] = (y * width + x) as u32;
+                        }
+                        cell[d] = ahead[x];
                     }
-                    visited.push(new_state.clone());
                 }
-                state = new_state;
             }
+        }
 
-            0
-        })
-        .sum()
-}
-
-fn step_block(
-    state: &State,
-    grid: &[Vec<bool>],
-    bounds: &(usize, usize),
-    block: &(usize, usize),
-) -> Option<State> {
-    match state.make_move(bounds) {
-        None => None,
-        Some(new_state) => {
-            if block == &(new_state.x, new_state.y) || grid[new_state.y][new_state.x] {
-                let right_state = state.turn_right();
-                Some(right_state)
-            } else {
-                Some(new_state)
+        Self {
+            width,
+            height,
+            stops,
+        }
+    }
+
+    /// Where the guard walking from `state` stops, with an extra obstacle at `block`
+    /// patched over the table.
+    pub fn stop(&self, state: &State, block: (usize, usize)) -> Option<(usize, usize)> {
+        let (x, y) = (state.x, state.y);
+        let stop = match self.stops[y * self.width + x][state.direction.index()] {
+            OFF_MAP => None,
+            cell => Some((cell as usize % self.width, cell as usize / self.width)),
+        };
+        let (dx, dy) = state.direction.delta();
+        let offset = (block.0 as isize - x as isize, block.1 as isize - y as isize);
+        let block_distance = match (dx, dy) {
+            (0, _) if offset.0 == 0 && offset.1 * dy > 0 => offset.1.unsigned_abs(),
+            (_, 0) if offset.1 == 0 && offset.0 * dx > 0 => offset.0.unsigned_abs(),
+            _ => return stop,
+        };
+        let stop_distance = stop.map(|(stop_x, stop_y)| stop_x.abs_diff(x) + stop_y.abs_diff(y));
+        if stop_distance.is_some_and(|distance| distance < block_distance) {
+            return stop;
+        }
+
+        Some((
+            (block.0 as isize - dx) as usize,
+            (block.1 as isize - dy) as usize,
+        ))
+    }
+
+    /// The loop the guard gets stuck in with an extra obstacle at `block`, found when it
+    /// turns twice in the same place facing the same way. `turns` is scratch space, so it
+    /// can be reused between simulations.
+    pub fn find_loop(
+        &self,
+        start: &State,
+        block: (usize, usize),
+        turns: &mut Turns,
+    ) -> Option<Cycle> {
+        turns.clear();
+        let mut state = start.clone();
+        while let Some((x, y)) = self.stop(&state, block) {
+            state = State::new(x, y, state.direction.turn_right());
+            let turn = (y * self.width + x) * 4 + state.direction.index();
+            if turns.insert(turn) {
+                continue;
             }
+
+            let first = turns.order.iter().position(|t| *t == turn).unwrap();
+            let decode =
+                |t: &usize| State::new(t / 4 % self.width, t / 4 / self.width, DIRECTIONS[t % 4]);
+            let cycle = turns.order[first..]
+                .iter()
+                .map(decode)
+                .collect::<Vec<State>>();
+            let len = cycle
+                .iter()
+                .zip(cycle.iter().cycle().skip(1))
+                .map(|(a, b)| a.x.abs_diff(b.x) + a.y.abs_diff(b.y) + 1)
+                .sum();
+
+            // The walk joins the loop on the segment leading into its first turn, either
+            // where that segment starts or, if the walk turned onto it, where the walk did.
+            let (last, stop) = (&cycle[cycle.len() - 1], &cycle[0]);
+            let on_last = |s: &State| {
+                s.direction == last.direction
+                    && last.x.abs_diff(s.x) + s.x.abs_diff(stop.x) == last.x.abs_diff(stop.x)
+                    && last.y.abs_diff(s.y) + s.y.abs_diff(stop.y) == last.y.abs_diff(stop.y)
+            };
+            let joined = match first {
+                0 => start.clone(),
+                _ => decode(&turns.order[first - 1]),
+            };
+            let start = if on_last(&joined) {
+                joined
+            } else {
+                last.clone()
+            };
+
+            return Some(Cycle { start, len });
+        }
+
+        None
+    }
+}
+
+/// A bitset of the turn states seen in one simulation. It remembers the order they were
+/// set in, so clearing it costs the number of turns rather than the size of the map.
+struct Turns {
+    bits: Vec<u64>,
+    order: Vec<usize>,
+}
+
+impl Turns {
+    pub fn new(len: usize) -> Self {
+        Self {
+            bits: vec![0; len.div_ceil(64)],
+            order: vec![],
+        }
+    }
+
+    /// Adds the turn, returning whether it was new.
+    pub fn insert(&mut self, turn: usize) -> bool {
+        let (word, bit) = (turn / 64, 1 << (turn % 64));
+        if self.bits[word] & bit != 0 {
+            return false;
+        }
+        self.bits[word] |= bit;
+        self.order.push(turn);
+
+        true
+    }
+
+    pub fn clear(&mut self) {
+        for turn in self.order.drain(..) {
+            self.bits[turn / 64] = 0;
         }
     }
 }
@@ -110,41 +540,22 @@ impl State {
         Self { x, y, direction }
     }
 
-    pub fn turn_right(&self) -> Self {
-        Self::new(self.x, self.y, self.direction.turn_right())
-    }
-
-    pub fn make_move(&self, bounds: &(usize, usize)) -> Option<Self> {
-        match self.direction {
-            Direction::Up => {
-                if self.y == 0 {
-                    None
-                } else {
-                    Some(State::new(self.x, self.y - 1, self.direction))
-                }
-            }
-            Direction::Right => {
-                if self.x >= bounds.0 - 1 {
-                    None
-                } else {
-                    Some(State::new(self.x + 1, self.y, self.direction))
-                }
-            }
-            Direction::Down => {
-                if self.y >= bounds.1 - 1 {
-                    None
-                } else {
-                    Some(State::new(self.x, self.y + 1, self.direction))
-                }
-            }
-            Direction::Left => {
-                if self.x == 0 {
-                    None
-                } else {
-                    Some(State::new(self.x - 1, self.y, self.direction))
-                }
-            }
+    /// One step forward, coming back in on the opposite edge if `wrap` is set and `None`
+    /// when walking off the map otherwise.
+    pub fn advance(&self, bounds: &(usize, usize), wrap: bool) -> Option<Self> {
+        let (dx, dy) = self.direction.delta();
+        let (width, height) = (bounds.0 as isize, bounds.1 as isize);
+        let (x, y) = (self.x as isize + dx, self.y as isize + dy);
+        let inside = (0..width).contains(&x) && (0..height).contains(&y);
+        if !inside && !wrap {
+            return None;
         }
+
+        Some(State::new(
+            x.rem_euclid(width) as usize,
+            y.rem_euclid(height) as usize,
+            self.direction,
+        ))
     }
 }
 
@@ -154,20 +565,163 @@ enum Direction {
     Right,
     Down,
     Left,
+    UpRight,
+    DownRight,
+    DownLeft,
+    UpLeft,
 }
 
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Right,
+    Direction::Down,
+    Direction::Left,
+];
+
+/// Every direction, clockwise from up.
+const COMPASS: [Direction; 8] = [
+    Direction::Up,
+    Direction::UpRight,
+    Direction::Right,
+    Direction::DownRight,
+    Direction::Down,
+    Direction::DownLeft,
+    Direction::Left,
+    Direction::UpLeft,
+];
+
 impl Direction {
+    /// Position in `DIRECTIONS`, for the tables the jump simulation keeps per heading.
+    /// Those only know the four cardinal directions.
+    pub fn index(self) -> usize {
+        debug_assert!(
+            DIRECTIONS.contains(&self),
+            "{self:?} is not a cardinal direction"
+        );
+        self as usize
+    }
+
+    pub fn from_symbol(c: char) -> Option<Direction> {
+        DIRECTIONS
+            .into_iter()
+            .find(|direction| direction.symbol() == c)
+    }
+
+    pub fn symbol(self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Right => '>',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::UpRight | Direction::DownLeft => '/',
+            Direction::DownRight | Direction::UpLeft => '\\',
+        }
+    }
+
+    pub fn delta(self) -> (isize, isize) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Right => (1, 0),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::UpRight => (1, -1),
+            Direction::DownRight => (1, 1),
+            Direction::DownLeft => (-1, 1),
+            Direction::UpLeft => (-1, -1),
+        }
+    }
+
     pub fn turn_right(self) -> Direction {
+        self.turn(Side::Right, false)
+    }
+
+    /// Turns a quarter towards `side`, or an eighth when moving diagonally is allowed.
+    pub fn turn(self, side: Side, diagonal: bool) -> Direction {
+        let i = COMPASS.iter().position(|d| *d == self).unwrap();
+        let step = if diagonal { 1 } else { 2 };
+        match side {
+            Side::Right => COMPASS[(i + step) % 8],
+            Side::Left => COMPASS[(i + 8 - step) % 8],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Side {
+    Right,
+    Left,
+}
+
+impl Side {
+    pub fn other(self) -> Side {
         match self {
-            Direction::Up => Direction::Right,
-            Direction::Right => Direction::Down,
-            Direction::Down => Direction::Left,
-            Direction::Left => Direction::Up,
+            Side::Right => Side::Left,
+            Side::Left => Side::Right,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Turning {
+    #[default]
+    Right,
+    Left,
+    /// Right first, then left, then right again...
+    Alternating,
+}
+
+/// How the guard behaves, the default being the puzzle's: turn right in front of an
+/// obstacle, only move up, down, left or right and leave when walking off the map.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Rules {
+    turning: Turning,
+    /// Turn an eighth at a time, so the guard also walks diagonally
+    diagonal: bool,
+    /// Walking off an edge comes back in on the opposite one
+    wrap: bool,
+}
+
+impl FromStr for Rules {
+    type Err = anyhow::Error;
+
+    /// Parses a comma separated list of `right`, `left`, `alternating`, `diagonal` and
+    /// `wrap`, e.g. `left,wrap`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut rules = Rules::default();
+        for rule in s.split(',') {
+            match rule.trim() {
+                "right" => rules.turning = Turning::Right,
+                "left" => rules.turning = Turning::Left,
+                "alternating" => rules.turning = Turning::Alternating,
+                "diagonal" => rules.diagonal = true,
+                "wrap" => rules.wrap = true,
+                rule => bail!("Unknown rule {rule:?}"),
+            }
         }
+
+        Ok(rules)
     }
 }
 
-fn parse_input(input: &'static str) -> Result<(State, Vec<Vec<bool>>)> {
+/// A square map with obstacles scattered at the given density and the guard in the
+/// middle, facing up.
+fn generate(size: usize, density: f64, seed: u64) -> String {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..size)
+        .map(|y| {
+            (0..size)
+                .map(|x| match (x, y) {
+                    _ if (x, y) == (size / 2, size / 2) => '^',
+                    _ if rng.random_bool(density) => '#',
+                    _ => '.',
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn parse_input(input: &str) -> Result<(State, Vec<Vec<bool>>)> {
     let mut start = None;
     let grid = input
         .lines()
@@ -175,8 +729,8 @@ fn parse_input(input: &'static str) -> Result<(State, Vec<Vec<bool>>)> {
         .map(|(y, line)| {
             line.char_indices()
                 .map(|(x, c)| {
-                    if c == '^' {
-                        start = Some(State::new(x, y, Direction::Up));
+                    if let Some(direction) = Direction::from_symbol(c) {
+                        start = Some(State::new(x, y, direction));
                     }
                     c == '#'
                 })
@@ -184,7 +738,11 @@ fn parse_input(input: &'static str) -> Result<(State, Vec<Vec<bool>>)> {
         })
         .collect::<Vec<Vec<bool>>>();
 
-    Ok((start.unwrap(), grid))
+    let Some(start) = start else {
+        bail!("No guard on the map");
+    };
+
+    Ok((start, grid))
 }
 
 #[cfg(test)]
@@ -224,4 +782,312 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_jumps_random() {
+        let mut rng = StdRng::seed_from_u64(6);
+        for _ in 0..50 {
+            let width = rng.random_range(1..20);
+            let height = rng.random_range(1..20);
+            let grid = (0..height)
+                .map(|_| (0..width).map(|_| rng.random_bool(0.15)).collect())
+                .collect::<Vec<Vec<bool>>>();
+            let x = rng.random_range(0..width);
+            let y = rng.random_range(0..height);
+            let start = State::new(x, y, DIRECTIONS[rng.random_range(0..4)]);
+
+            let jumps = Jumps::new(&grid);
+            let mut turns = Turns::new(width * height * 4);
+            for block_y in 0..height {
+                for block_x in 0..width {
+                    let block = (block_x, block_y);
+                    let cycle = match GuardWalk::new(start.clone(), &grid)
+                        .with_block(block)
+                        .finish()
+                    {
+                        Outcome::Looped {
+                            cycle_start,
+                            cycle_len,
+                        } => Some(Cycle {
+                            start: cycle_start,
+                            len: cycle_len,
+                        }),
+                        Outcome::Exited(_) => None,
+                    };
+                    assert_eq!(
+                        jumps.find_loop(&start, block, &mut turns),
+                        cycle,
+                        "{start:?} {block:?} {grid:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate() -> Result<()> {
+        let data = generate(60, 0.05, 1);
+        let (start, grid) = parse_input(&data)?;
+        assert_eq!((grid[0].len(), grid.len()), (60, 60));
+        assert_eq!(start, State::new(30, 30, Direction::Up));
+        assert_eq!(
+            solve_with_rules(&start, &grid, Rules::default()),
+            (part_one(start.clone(), &grid), part_two(start, &grid))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_traps_testdata() -> Result<()> {
+        let (start, grid) = parse_input(TESTDATA)?;
+        let traps = traps(&start, &grid);
+        assert_eq!(
+            traps.iter().map(|trap| trap.block).collect::<Vec<_>>(),
+            vec![(3, 6), (6, 7), (3, 8), (1, 8), (7, 7), (7, 9)]
+        );
+
+        let trap = traps.iter().find(|trap| trap.block == (3, 6)).unwrap();
+        assert_eq!(trap.cycle.start, State::new(4, 6, Direction::Up));
+        assert_eq!(trap.cycle.len, 22);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_traps_match_guard_walk_testdata() -> Result<()> {
+        let (start, grid) = parse_input(TESTDATA)?;
+        for trap in traps(&start, &grid) {
+            let outcome = GuardWalk::new(start.clone(), &grid)
+                .with_block(trap.block)
+                .finish();
+            assert_eq!(
+                outcome,
+                Outcome::Looped {
+                    cycle_start: trap.cycle.start,
+                    cycle_len: trap.cycle.len
+                },
+                "{:?}",
+                trap.block
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_testdata() -> Result<()> {
+        let (start, grid) = parse_input(TESTDATA)?;
+        let traps = traps(&start, &grid);
+        let trap = traps.iter().find(|trap| trap.block == (3, 6)).unwrap();
+        assert_eq!(
+            render(&start, &grid, trap),
+            [
+                "....#.....",
+                "....+---+#",
+                "....|...|.",
+                "..#.|...|.",
+                "..XX|XX#|.",
+                "..X.|.X.|.",
+                ".#XO^---+.",
+                ".XXXXXXX#.",
+                "#XXXXXXX..",
+                "......#X..",
+            ]
+            .join("\n")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_guard_walk_testdata() -> Result<()> {
+        let (start, grid) = parse_input(TESTDATA)?;
+        let mut walk = GuardWalk::new(start.clone(), &grid);
+        assert_eq!(walk.next(), Some(start.clone()));
+        assert_eq!(walk.next(), Some(State::new(4, 5, Direction::Up)));
+        assert_eq!(walk.outcome(), None);
+        assert_eq!(
+            walk.finish(),
+            Outcome::Exited(State::new(7, 9, Direction::Down))
+        );
+
+        let outcome = GuardWalk::new(start, &grid).with_block((3, 6)).finish();
+        assert_eq!(
+            outcome,
+            Outcome::Looped {
+                cycle_start: State::new(4, 6, Direction::Up),
+                cycle_len: 22,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_symbols() -> Result<()> {
+        let (start, _) = parse_input("...\n.<.\n...")?;
+        assert_eq!(start, State::new(1, 1, Direction::Left));
+        let (start, _) = parse_input("..v")?;
+        assert_eq!(start, State::new(2, 0, Direction::Down));
+        assert!(parse_input("...").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rules_default_testdata() -> Result<()> {
+        let (start, grid) = parse_input(TESTDATA)?;
+        assert_eq!(solve_with_rules(&start, &grid, "right".parse()?), (41, 6));
+        assert_eq!(solve_with_rules(&start, &grid, Rules::default()), (41, 6));
+        assert!("right,sideways".parse::<Rules>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_left_turns_mirror_right_turns() -> Result<()> {
+        let (start, grid) = parse_input(TESTDATA)?;
+        let width = grid[0].len();
+        let mirrored = grid
+            .iter()
+            .map(|row| row.iter().rev().copied().collect())
+            .collect::<Vec<Vec<bool>>>();
+        let mirrored_start = State::new(width - 1 - start.x, start.y, start.direction);
+        assert_eq!(
+            solve_with_rules(&mirrored_start, &mirrored, "left".parse()?),
+            (41, 6)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alternating_turns() -> Result<()> {
+        let (start, grid) = parse_input(".#..\n...#\n.^..")?;
+        assert_eq!(
+            GuardWalk::new(start.clone(), &grid).finish(),
+            Outcome::Exited(State::new(2, 2, Direction::Down))
+        );
+        let walk = GuardWalk::new(start, &grid).with_rules("alternating".parse()?);
+        assert_eq!(
+            walk.collect::<Vec<State>>(),
+            vec![
+                State::new(1, 2, Direction::Up),
+                State::new(1, 1, Direction::Up),
+                State::new(1, 1, Direction::Right),
+                State::new(2, 1, Direction::Right),
+                State::new(2, 1, Direction::Up),
+                State::new(2, 0, Direction::Up),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diagonal_moves() -> Result<()> {
+        let (start, grid) = parse_input("#..\n...\n^..")?;
+        let rules = "diagonal".parse()?;
+        assert_eq!(
+            GuardWalk::new(start.clone(), &grid)
+                .with_rules(rules)
+                .finish(),
+            Outcome::Exited(State::new(1, 0, Direction::UpRight))
+        );
+        assert_eq!(
+            walked_positions(start, &grid, rules),
+            vec![(0, 2), (0, 1), (1, 0)]
+        );
+
+        Ok(())
+    }
+
+    /// Walks cell by cell remembering every state, rather than only those after turns.
+    fn finish_every_state(start: &State, grid: &[Vec<bool>], rules: Rules) -> Outcome {
+        let bounds = (grid[0].len(), grid.len());
+        let mut side = match rules.turning {
+            Turning::Left => Side::Left,
+            _ => Side::Right,
+        };
+        let mut seen = HashMap::new();
+        let mut walk = vec![];
+        let mut state = start.clone();
+        loop {
+            if let Some(&step) = seen.get(&(state.clone(), side)) {
+                return Outcome::Looped {
+                    cycle_len: walk.len() - step,
+                    cycle_start: walk.swap_remove(step),
+                };
+            }
+            seen.insert((state.clone(), side), walk.len());
+            walk.push(state.clone());
+            let Some(next) = state.advance(&bounds, rules.wrap) else {
+                return Outcome::Exited(state);
+            };
+            state = if grid[next.y][next.x] {
+                let direction = state.direction.turn(side, rules.diagonal);
+                if rules.turning == Turning::Alternating {
+                    side = side.other();
+                }
+                State::new(state.x, state.y, direction)
+            } else {
+                next
+            };
+        }
+    }
+
+    #[test]
+    fn test_guard_walk_random() {
+        let mut rng = StdRng::seed_from_u64(48);
+        for _ in 0..2000 {
+            let width = rng.random_range(1..12);
+            let height = rng.random_range(1..12);
+            let grid = (0..height)
+                .map(|_| (0..width).map(|_| rng.random_bool(0.2)).collect())
+                .collect::<Vec<Vec<bool>>>();
+            let rules = Rules {
+                turning: [Turning::Right, Turning::Left, Turning::Alternating]
+                    [rng.random_range(0..3)],
+                diagonal: rng.random_bool(0.5),
+                wrap: rng.random_bool(0.5),
+            };
+            let directions = if rules.diagonal {
+                &COMPASS[..]
+            } else {
+                &DIRECTIONS[..]
+            };
+            let start = State::new(
+                rng.random_range(0..width),
+                rng.random_range(0..height),
+                directions[rng.random_range(0..directions.len())],
+            );
+
+            assert_eq!(
+                GuardWalk::new(start.clone(), &grid)
+                    .with_rules(rules)
+                    .finish(),
+                finish_every_state(&start, &grid, rules),
+                "{start:?} {rules:?} {grid:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_wrap_around() -> Result<()> {
+        let (start, grid) = parse_input("...\n>..\n...")?;
+        let rules = "wrap".parse()?;
+        assert_eq!(
+            GuardWalk::new(start.clone(), &grid)
+                .with_rules(rules)
+                .finish(),
+            Outcome::Looped {
+                cycle_start: start.clone(),
+                cycle_len: 3,
+            }
+        );
+        assert_eq!(solve_with_rules(&start, &grid, rules), (3, 3));
+
+        Ok(())
+    }
 }
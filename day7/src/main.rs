@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use nom::{
     bytes::complete::tag,
     character::complete::{self, line_ending},
@@ -26,68 +26,221 @@ fn main() -> Result<()> {
     println!("Result part two: {result}");
     println!("Time spent: {took}");
 
+    if let Some(list) = std::env::args()
+        .skip_while(|arg| arg != "--operators")
+        .nth(1)
+    {
+        let operators = parse_operators(&list)?;
+        let operators = operators
+            .iter()
+            .map(|operator| operator.as_ref())
+            .collect::<Vec<&dyn Operator>>();
+        let (took, result) = took::took(|| solve(&input, &operators));
+        println!("Result with {list}: {result}");
+        println!("Time spent: {took}");
+    }
+
     Ok(())
 }
 
 fn part_one(input: &[(u64, Vec<u64>)]) -> u64 {
-    solve(input, false)
+    solve(input, &[&Add, &Multiply])
 }
 
 fn part_two(input: &[(u64, Vec<u64>)]) -> u64 {
-    solve(input, true)
+    solve(input, &[&Add, &Multiply, &Concatenate::DECIMAL])
 }
 
-fn solve(input: &[(u64, Vec<u64>)], part2: bool) -> u64 {
+fn solve(input: &[(u64, Vec<u64>)], operators: &[&dyn Operator]) -> u64 {
     input
         .iter()
-        .filter(|(goal, factors)| solveable(goal, factors, part2))
+        .filter(|(goal, factors)| solvable(*goal, factors, operators))
         .map(|(goal, _)| goal)
         .sum()
 }
 
-fn solveable(goal: &u64, factors: &[u64], part2: bool) -> bool {
-    solve_internally(goal, factors, 0, Operator::Add, part2)
-        || solve_internally(goal, factors, 1, Operator::Multiply, part2)
-        || part2 && solve_internally(goal, factors, 1, Operator::Concatenate, part2)
+/// A binary operator placed between two numbers of an equation, which is evaluated left to
+/// right.
+trait Operator {
+    /// `None` when the result is undefined or does not fit in a `u64`.
+    fn apply(&self, a: u64, b: u64) -> Option<u64>;
+
+    /// The `a` for which `apply(a, b)` gives `result`. Lets the solver work backwards
+    /// from the goal, but operators that cannot tell may leave it [`Inverse::Unknown`].
+    fn inverse(&self, _result: u64, _b: u64) -> Inverse {
+        Inverse::Unknown
+    }
+}
+
+enum Inverse {
+    /// Only this `a` gives the result
+    Unique(u64),
+    /// No `a` gives the result
+    Impossible,
+    /// Several `a` might give the result, so they have to be searched for
+    Unknown,
+}
+
+impl Inverse {
+    fn from_option(a: Option<u64>) -> Self {
+        a.map_or(Inverse::Impossible, Inverse::Unique)
+    }
+}
+
+struct Add;
+
+impl Operator for Add {
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_add(b)
+    }
+
+    fn inverse(&self, result: u64, b: u64) -> Inverse {
+        Inverse::from_option(result.checked_sub(b))
+    }
+}
+
+struct Multiply;
+
+impl Operator for Multiply {
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_mul(b)
+    }
+
+    fn inverse(&self, result: u64, b: u64) -> Inverse {
+        match (result, b) {
+            (0, 0) => Inverse::Unknown,
+            (_, 0) => Inverse::Impossible,
+            _ if result.is_multiple_of(b) => Inverse::Unique(result / b),
+            _ => Inverse::Impossible,
+        }
+    }
+}
+
+/// Subtraction that stays in the natural numbers.
+struct Subtract;
+
+impl Operator for Subtract {
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_sub(b)
+    }
+
+    fn inverse(&self, result: u64, b: u64) -> Inverse {
+        Inverse::from_option(result.checked_add(b))
+    }
+}
+
+/// Integer division, rounding down.
+struct Divide;
+
+impl Operator for Divide {
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_div(b)
+    }
+}
+
+struct Power;
+
+impl Operator for Power {
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_pow(b.try_into().ok()?)
+    }
+}
+
+/// Writes the digits of `b` after those of `a` in the given base, which is at least 2.
+struct Concatenate {
+    base: u64,
 }
 
-fn solve_internally(
-    goal: &u64,
-    factors: &[u64],
-    subtotal: u64,
-    operator: Operator,
-    part2: bool,
-) -> bool {
-    let new_subtotal = match operator {
-        Operator::Add => subtotal + factors[0],
-        Operator::Multiply => subtotal * factors[0],
-        Operator::Concatenate => subtotal * 10u64.pow(factors[0].ilog10() + 1) + factors[0],
-    };
+impl Concatenate {
+    const DECIMAL: Concatenate = Concatenate { base: 10 };
+
+    /// The smallest power of the base above `b`, so `a * shift + b` concatenates them.
+    fn shift(&self, b: u64) -> Option<u64> {
+        let mut shift = self.base;
+        while shift <= b {
+            shift = shift.checked_mul(self.base)?;
+        }
+
+        Some(shift)
+    }
+}
+
+impl Operator for Concatenate {
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        a.checked_mul(self.shift(b)?)?.checked_add(b)
+    }
+
+    fn inverse(&self, result: u64, b: u64) -> Inverse {
+        match self.shift(b) {
+            Some(shift) if result % shift == b => Inverse::Unique(result / shift),
+            _ => Inverse::Impossible,
+        }
+    }
+}
 
-    if subtotal > *goal {
-        return false;
+/// Whether some choice of operators between the factors gives the goal. Works backwards
+/// from the goal, undoing the last factor with every operator, and searches forwards
+/// for the operators that cannot be undone.
+fn solvable(goal: u64, factors: &[u64], operators: &[&dyn Operator]) -> bool {
+    match factors {
+        [] => false,
+        [first] => *first == goal,
+        [rest @ .., last] => operators
+            .iter()
+            .any(|operator| match operator.inverse(goal, *last) {
+                Inverse::Unique(a) => solvable(a, rest, operators),
+                Inverse::Impossible => false,
+                Inverse::Unknown => reaches(rest, operators, &|subtotal| {
+                    operator.apply(subtotal, *last) == Some(goal)
+                }),
+            }),
     }
+}
 
-    if factors.len() == 1 {
-        return new_subtotal == *goal;
+/// Whether some choice of operators between the factors gives a value accepted by
+/// `check`, trying every one going forwards.
+fn reaches(factors: &[u64], operators: &[&dyn Operator], check: &dyn Fn(u64) -> bool) -> bool {
+    fn forward(
+        subtotal: u64,
+        factors: &[u64],
+        operators: &[&dyn Operator],
+        check: &dyn Fn(u64) -> bool,
+    ) -> bool {
+        match factors {
+            [] => check(subtotal),
+            [next, rest @ ..] => operators.iter().any(|operator| {
+                operator
+                    .apply(subtotal, *next)
+                    .is_some_and(|subtotal| forward(subtotal, rest, operators, check))
+            }),
+        }
     }
 
-    solve_internally(goal, &factors[1..], new_subtotal, Operator::Add, part2)
-        || solve_internally(goal, &factors[1..], new_subtotal, Operator::Multiply, part2)
-        || (part2
-            && solve_internally(
-                goal,
-                &factors[1..],
-                new_subtotal,
-                Operator::Concatenate,
-                part2,
-            ))
+    match factors {
+        [first, rest @ ..] => forward(*first, rest, operators, check),
+        [] => false,
+    }
 }
 
-enum Operator {
-    Add,
-    Multiply,
-    Concatenate,
+/// Parses a comma separated list of operator names, e.g. `add,subtract,concat2` where
+/// `concat` concatenates in decimal and `concatN` in base `N`.
+fn parse_operators(list: &str) -> Result<Vec<Box<dyn Operator>>> {
+    list.split(',')
+        .map(|name| -> Result<Box<dyn Operator>> {
+            Ok(match name.trim() {
+                "add" => Box::new(Add),
+                "multiply" => Box::new(Multiply),
+                "subtract" => Box::new(Subtract),
+                "divide" => Box::new(Divide),
+                "power" => Box::new(Power),
+                "concat" => Box::new(Concatenate::DECIMAL),
+                name => match name.strip_prefix("concat").map(str::parse::<u64>) {
+                    Some(Ok(base)) if base >= 2 => Box::new(Concatenate { base }),
+                    _ => bail!("Unknown operator {name:?}"),
+                },
+            })
+        })
+        .collect()
 }
 
 fn parse_input(input: &'static str) -> Result<Vec<(u64, Vec<u64>)>> {
@@ -112,6 +265,7 @@ fn parse_line(input: &str) -> IResult<&str, (u64, Vec<u64>)> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     const TESTDATA: &str = include_str!("test.txt");
 
@@ -142,4 +296,68 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_other_operators() {
+        assert!(solvable(5, &[10, 5], &[&Add, &Subtract]));
+        assert!(!solvable(5, &[10, 5], &[&Add, &Multiply]));
+        assert!(solvable(2, &[7, 3], &[&Divide]));
+        assert!(solvable(4, &[2, 9, 4], &[&Divide, &Add]));
+        assert!(solvable(8, &[2, 3], &[&Power]));
+        assert!(solvable(64, &[2, 3, 2], &[&Power]));
+        assert!(solvable(0, &[5, 0], &[&Multiply]));
+        // 1 and 101 in binary
+        assert!(solvable(13, &[1, 5], &[&Concatenate { base: 2 }]));
+        assert!(!solvable(13, &[1, 5], &[&Concatenate::DECIMAL]));
+        assert!(solvable(156, &[15, 6], &[&Concatenate::DECIMAL]));
+    }
+
+    #[test]
+    fn test_solvable_matches_forward_search_random() {
+        let binary = Concatenate { base: 2 };
+        let operators: [&dyn Operator; 7] = [
+            &Add,
+            &Multiply,
+            &Subtract,
+            &Divide,
+            &Power,
+            &Concatenate::DECIMAL,
+            &binary,
+        ];
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..5000 {
+            let set = operators
+                .iter()
+                .filter(|_| rng.random_bool(0.5))
+                .copied()
+                .collect::<Vec<&dyn Operator>>();
+            let factors = (0..rng.random_range(1..6))
+                .map(|_| rng.random_range(0..8))
+                .collect::<Vec<u64>>();
+            let goal = rng.random_range(0..100);
+            assert_eq!(
+                solvable(goal, &factors, &set),
+                reaches(&factors, &set, &|value| value == goal),
+                "{goal}: {factors:?} with {} operators",
+                set.len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_operators() -> Result<()> {
+        let input = parse_input(TESTDATA)?;
+        let operators = parse_operators("add, multiply,concat")?;
+        let operators = operators
+            .iter()
+            .map(|operator| operator.as_ref())
+            .collect::<Vec<&dyn Operator>>();
+        assert_eq!(solve(&input, &operators), 11387);
+
+        assert_eq!(parse_operators("subtract,divide,power,concat16")?.len(), 4);
+        assert!(parse_operators("add,concat1").is_err());
+        assert!(parse_operators("modulo").is_err());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,45 @@
+//! The nom parser for the grids of characters many puzzles take as input, so the days
+//! only say how to read a single cell.
+
+use nom::{
+    character::complete::line_ending,
+    error::ParseError,
+    multi::{many1, separated_list1},
+    Parser,
+};
+
+/// One row of at least one `cell` per line, up to the first line that does not start with
+/// a cell, e.g. the blank line before the next grid.
+pub fn grid<'a, O, E: ParseError<&'a str>>(
+    cell: impl Parser<&'a str, Output = O, Error = E>,
+) -> impl Parser<&'a str, Output = Vec<Vec<O>>, Error = E> {
+    separated_list1(line_ending, many1(cell))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nom::{
+        character::complete::one_of, combinator::map, multi::separated_list1, sequence::pair,
+        IResult,
+    };
+
+    #[test]
+    fn test_grids() {
+        let result: IResult<&str, Vec<Vec<Vec<bool>>>> = separated_list1(
+            pair(line_ending, line_ending),
+            grid(map(one_of("#."), |c| c == '#')),
+        )
+        .parse("#.\n.#\n\n##\r\n..\nrest");
+        assert_eq!(
+            result,
+            Ok((
+                "\nrest",
+                vec![
+                    vec![vec![true, false], vec![false, true]],
+                    vec![vec![true, true], vec![false, false]],
+                ]
+            ))
+        );
+    }
+}